@@ -17,7 +17,7 @@ fn file_benches(c: &mut Criterion) {
 	r.block_on(async {
 		let path = "/tmp/x";
 		let mut f = File::create(&path).await.expect("create failed");
-		let buf = vec!['@' as u8; SIZE];
+		let buf = vec![b'@'; SIZE];
 		f.write_all(&buf).await.expect("write all failed");
 		f.flush().await.expect("flush failed");
 	});
@@ -37,7 +37,23 @@ fn file_benches(c: &mut Criterion) {
 				}));
 			}
 
-			while let Some(_) = futs.next().await {}
+			while futs.next().await.is_some() {}
+		})
+	});
+
+	c.bench_function("MmapFile copy_buf", |b| {
+		b.to_async(&r).iter(|| async {
+			let mut f = fm.get("/tmp/x").await.unwrap();
+			let n = tokio::io::copy_buf(&mut f, &mut tokio::io::sink()).await.unwrap();
+			assert_eq!(n, SIZE as u64);
+		})
+	});
+
+	c.bench_function("MmapFile copy", |b| {
+		b.to_async(&r).iter(|| async {
+			let mut f = fm.get("/tmp/x").await.unwrap();
+			let n = tokio::io::copy(&mut f, &mut tokio::io::sink()).await.unwrap();
+			assert_eq!(n, SIZE as u64);
 		})
 	});
 
@@ -52,7 +68,7 @@ fn file_benches(c: &mut Criterion) {
 					assert_eq!(n, SIZE);
 				}));
 			}
-			while let Some(_) = futs.next().await {}
+			while futs.next().await.is_some() {}
 		})
 	});
 }
@@ -0,0 +1,155 @@
+use crate::Result;
+use std::sync::{
+	Arc, LazyLock,
+	atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed},
+};
+use tokio::{sync::Semaphore, task::spawn_blocking};
+
+/// The number of concurrent jobs the global pool allows.
+pub const DEFAULT_BLOCKING_LIMIT: usize = 64;
+
+static GLOBAL: LazyLock<Arc<BlockingPool>> = LazyLock::new(|| Arc::new(BlockingPool::new(DEFAULT_BLOCKING_LIMIT)));
+
+/// Bounds the crate's blocking work (opening and populating mappings, fsyncs) on tokio's blocking pool.
+///
+/// Jobs beyond the limit wait in a queue instead of each grabbing a blocking thread, so a burst of
+/// large mappings can't starve the rest of the application's `spawn_blocking` users. Every pool
+/// exposes queue-depth counters through [`BlockingPool::stats`].
+///
+/// Operations that take a [`crate::Config`], and the handles opened with one, use the pool set by
+/// [`crate::Config::blocking_pool`]. The free functions mirroring `tokio::fs` ([`crate::read`],
+/// [`crate::read_to_string`], [`crate::write`] and [`crate::copy`]) and [`crate::fs_info`] always use
+/// [`BlockingPool::global`].
+#[derive(Debug)]
+pub struct BlockingPool {
+	sem: Arc<Semaphore>,
+	limit: usize,
+	counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+	queued: AtomicUsize,
+	running: AtomicUsize,
+	completed: AtomicU64,
+}
+
+/// A snapshot of a [`BlockingPool`]'s counters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockingStats {
+	/// The maximum number of concurrently running jobs.
+	pub limit: usize,
+	/// Jobs waiting for a slot.
+	pub queued: usize,
+	/// Jobs currently running on a blocking thread.
+	pub running: usize,
+	/// Jobs finished since the pool was created.
+	pub completed: u64,
+}
+
+/// Increments a gauge for as long as it's alive.
+struct Gauge<'a>(&'a AtomicUsize);
+
+impl<'a> Gauge<'a> {
+	fn inc(n: &'a AtomicUsize) -> Self {
+		n.fetch_add(1, Relaxed);
+		Self(n)
+	}
+}
+
+impl Drop for Gauge<'_> {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Relaxed);
+	}
+}
+
+impl BlockingPool {
+	/// Creates a pool running at most `limit` jobs at a time.
+	///
+	/// # Panics
+	///
+	/// This function will panic if `limit` is 0.
+	pub fn new(limit: usize) -> Self {
+		assert!(limit > 0, "blocking pool limit must be positive");
+		Self {
+			sem: Arc::new(Semaphore::new(limit)),
+			limit,
+			counters: Arc::default(),
+		}
+	}
+
+	/// Returns the process-wide default pool.
+	pub fn global() -> &'static Arc<BlockingPool> {
+		&GLOBAL
+	}
+
+	/// Returns the current counters.
+	pub fn stats(&self) -> BlockingStats {
+		BlockingStats {
+			limit: self.limit,
+			queued: self.counters.queued.load(Relaxed),
+			running: self.counters.running.load(Relaxed),
+			completed: self.counters.completed.load(Relaxed),
+		}
+	}
+
+	/// Runs `f` on tokio's blocking pool once a slot is free.
+	///
+	/// The slot stays taken until `f` returns, even if the returned future is dropped first.
+	pub(crate) async fn spawn<T, F>(&self, f: F) -> Result<T>
+	where
+		F: FnOnce() -> Result<T> + Send + 'static,
+		T: Send + 'static,
+	{
+		let queued = Gauge::inc(&self.counters.queued);
+		let permit = self
+			.sem
+			.clone()
+			.acquire_owned()
+			.await
+			.expect("semaphore is never closed");
+		drop(queued);
+
+		let counters = self.counters.clone();
+		spawn_blocking(move || {
+			let _permit = permit;
+			let _running = Gauge::inc(&counters.running);
+			let res = f();
+			counters.completed.fetch_add(1, Relaxed);
+			res
+		})
+		.await?
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::mpsc;
+	use tokio::sync::oneshot;
+
+	#[tokio::test]
+	async fn test_blocking_pool_limit() -> Result<()> {
+		let pool = BlockingPool::new(1);
+		let (started_tx, started_rx) = oneshot::channel();
+		let (release_tx, release_rx) = mpsc::channel::<()>();
+		let hold = move || {
+			let _ = started_tx.send(());
+			let _ = release_rx.recv();
+			Ok(())
+		};
+		// the held job takes the only slot, so the second one queues until it's released
+		let watch = async {
+			started_rx.await.expect("held job started");
+			let stats = pool.stats();
+			release_tx.send(()).expect("held job is waiting");
+			stats
+		};
+		let (a, b, stats) = tokio::join!(pool.spawn(hold), pool.spawn(|| Ok(())), watch);
+		a?;
+		b?;
+		assert_eq!((stats.running, stats.queued), (1, 1));
+		assert_eq!(pool.stats().completed, 2);
+		Ok(())
+	}
+}
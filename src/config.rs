@@ -0,0 +1,364 @@
+use crate::{BlockingPool, Result};
+use std::{
+	fmt,
+	fs::{File as StdFile, Metadata, Permissions},
+	io::{Error, ErrorKind},
+	os::unix::fs::{PermissionsExt, fchown},
+	sync::Arc,
+	time::Duration,
+};
+use tokio::fs::File as TokioFile;
+
+/// Settings shared by [`crate::FileMap`] and [`crate::MmapFile`].
+///
+/// Construct it once and hand clones to every map so the whole application uses consistent limits.
+///
+/// # Example
+///
+/// ```
+/// # use async_mmap_file::{Config, FileMap};
+/// # use std::time::Duration;
+/// let config = Config::new()
+///     .max_file_size(Some(1 << 30))
+///     .lock_timeout(Some(Duration::from_secs(5)));
+/// let file_map = FileMap::with_config(config);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Config {
+	pub(crate) populate: bool,
+	pub(crate) max_file_size: Option<u64>,
+	pub(crate) lock_timeout: Option<Duration>,
+	pub(crate) seal: bool,
+	pub(crate) io_timeout: Option<Duration>,
+	pub(crate) blocking_pool: Arc<BlockingPool>,
+	pub(crate) file_mode: Option<u32>,
+	pub(crate) file_owner: (Option<u32>, Option<u32>),
+	pub(crate) truncation_check: bool,
+	pub(crate) register: bool,
+	pub(crate) max_inflight_gets: Option<usize>,
+	pub(crate) max_inflight_writers: Option<usize>,
+	pub(crate) strict_eof: bool,
+	pub(crate) populate_max_size: Option<u64>,
+	pub(crate) huge_pages: bool,
+	pub(crate) read_chunk_size: usize,
+	pub(crate) read_budget: Option<usize>,
+	pub(crate) validator: Option<Validator>,
+	pub(crate) durability: Durability,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			populate: true,
+			max_file_size: None,
+			lock_timeout: None,
+			seal: false,
+			io_timeout: None,
+			blocking_pool: BlockingPool::global().clone(),
+			file_mode: None,
+			file_owner: (None, None),
+			truncation_check: false,
+			register: false,
+			max_inflight_gets: None,
+			max_inflight_writers: None,
+			strict_eof: false,
+			populate_max_size: None,
+			huge_pages: false,
+			read_chunk_size: 4096,
+			read_budget: None,
+			validator: None,
+			durability: Durability::Flush,
+		}
+	}
+}
+
+impl Config {
+	/// Returns the default configuration.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Whether mappings are prefaulted at open time (`MAP_POPULATE`), defaults to `true`.
+	pub fn populate(mut self, populate: bool) -> Self {
+		self.populate = populate;
+		self
+	}
+
+	/// The largest file that is prefaulted when [`Config::populate`] is set, defaults to none (all files).
+	///
+	/// Populating pays off for small hot files but makes opening multi-GB files stall for as long as
+	/// reading them would. Larger files are mapped lazily, [`crate::MmapFile::is_populated`] tells which
+	/// way a mapping went.
+	pub fn populate_max_size(mut self, max: Option<u64>) -> Self {
+		self.populate_max_size = max;
+		self
+	}
+
+	/// Whether read-only mappings ask for transparent huge pages (`MADV_HUGEPAGE`), defaults to `false`.
+	///
+	/// Backing multi-GB mappings with 2MB pages cuts TLB misses considerably. It's a hint, kernels without
+	/// THP support for the file's file system keep using regular pages. Prefaulting then happens after the
+	/// advice, so it can already fault in huge pages.
+	pub fn huge_pages(mut self, huge: bool) -> Self {
+		self.huge_pages = huge;
+		self
+	}
+
+	/// The largest file that will be mapped, larger files fail with `ErrorKind::FileTooLarge`.
+	pub fn max_file_size(mut self, max: Option<u64>) -> Self {
+		self.max_file_size = max;
+		self
+	}
+
+	/// How long [`crate::FileMap::writer`] waits for a path before failing with `ErrorKind::TimedOut`.
+	///
+	/// `None`, the default, waits forever.
+	pub fn lock_timeout(mut self, timeout: Option<Duration>) -> Self {
+		self.lock_timeout = timeout;
+		self
+	}
+
+	/// Whether files are copied into a sealed memfd before being mapped, defaults to `false`.
+	///
+	/// Sealed mappings can't be modified or truncated by anyone, which removes the SIGBUS and
+	/// torn-read hazards of mapping files that other processes may touch. The cost is a full copy of
+	/// the file into memory at open time, so this is meant for immutable blobs of reasonable size.
+	pub fn seal(mut self, seal: bool) -> Self {
+		self.seal = seal;
+		self
+	}
+
+	/// A deadline for each blocking-prone operation (opening and mapping files, fsyncs), defaults to none.
+	///
+	/// An operation exceeding it fails with `ErrorKind::TimedOut`. The blocking thread itself can't be
+	/// interrupted and finishes in the background, but callers (and the runtime) are no longer stuck
+	/// behind a hung mount.
+	pub fn io_timeout(mut self, timeout: Option<Duration>) -> Self {
+		self.io_timeout = timeout;
+		self
+	}
+
+	/// The pool used for blocking work, defaults to [`BlockingPool::global`].
+	///
+	/// Give a subsystem its own pool to keep its mapping bursts from delaying everyone else's.
+	pub fn blocking_pool(mut self, pool: Arc<BlockingPool>) -> Self {
+		self.blocking_pool = pool;
+		self
+	}
+
+	/// The permission bits of files opened for writing, defaults to none (`0o666` minus the umask).
+	///
+	/// The mode is set explicitly after opening, so it's not affected by the umask and also applies
+	/// to existing files that are rewritten.
+	pub fn file_mode(mut self, mode: Option<u32>) -> Self {
+		self.file_mode = mode;
+		self
+	}
+
+	/// The owner and group of files opened for writing, defaults to none (the process' own).
+	///
+	/// Changing the owner usually requires `CAP_CHOWN`; failing to apply it fails the open.
+	pub fn file_owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+		self.file_owner = (uid, gid);
+		self
+	}
+
+	/// Whether reads check the file's current length before touching the mapping, defaults to `false`.
+	///
+	/// Reading pages of a mapping past the end of a file that another process truncated kills the
+	/// process with `SIGBUS`. With this set, reads through `AsyncRead`, `read_at`,
+	/// [`crate::MmapFile::copy_range_to_vec`] and the accessors handing out parts of the mapping (`get`,
+	/// `to_bytes`, `slice`, `read_slice_at` and the typed `read_*_at`) `fstat` the file first and fail
+	/// with a [`crate::Truncated`] error instead. This narrows the window but can't close it: a
+	/// truncation between the check and the copy still faults, as does one while a returned slice or
+	/// `Bytes` is alive. Files from untrusted writers should be opened with [`Config::seal`] instead,
+	/// which never needs the check.
+	pub fn truncation_check(mut self, check: bool) -> Self {
+		self.truncation_check = check;
+		self
+	}
+
+	/// Whether mappings are recorded in the process-wide registry, defaults to `false`.
+	///
+	/// Registered mappings are listed by [`crate::live_mappings`] for as long as any handle to them is
+	/// alive, which makes leaked mappings visible at runtime. Registering costs a global lock per open.
+	pub fn register(mut self, register: bool) -> Self {
+		self.register = register;
+		self
+	}
+
+	/// How many files a [`crate::FileMap`] opens and maps for `get` at once, defaults to unlimited.
+	///
+	/// Only cache misses count, further ones wait for a slot. Each map enforces its own limit, so a flood
+	/// of requests for one map can't take every blocking thread and file descriptor from the others.
+	pub fn max_inflight_gets(mut self, max: Option<usize>) -> Self {
+		self.max_inflight_gets = max;
+		self
+	}
+
+	/// How many writers of a [`crate::FileMap`] may be alive at once, defaults to unlimited.
+	///
+	/// `try_writer` fails with an `ErrorKind::Other` error while the limit is reached, so `writer`
+	/// waits for a slot the same way it waits for a path's lock.
+	pub fn max_inflight_writers(mut self, max: Option<usize>) -> Self {
+		self.max_inflight_writers = max;
+		self
+	}
+
+	/// Whether reads starting past the end of a mapping fail, defaults to `false`.
+	///
+	/// By default such reads (e.g. [`crate::MmapFile::read_at`] with an offset beyond the file) return 0
+	/// bytes like reads at the end do, matching `pread(2)`. With this set they fail with
+	/// `ErrorKind::UnexpectedEof` instead, which catches offset bugs early.
+	pub fn strict_eof(mut self, strict: bool) -> Self {
+		self.strict_eof = strict;
+		self
+	}
+
+	/// The most bytes a single `poll_read` of a [`crate::MmapFile`] copies, defaults to 4096.
+	///
+	/// Reads are capped so that one call doesn't copy for too long, but the default makes `read_to_end`
+	/// of large files poll thousands of times. Raise it for bulk reads into large buffers.
+	///
+	/// # Panics
+	///
+	/// Panics if `size` is 0.
+	pub fn read_chunk_size(mut self, size: usize) -> Self {
+		assert!(size > 0, "read chunk size must be non-zero");
+		self.read_chunk_size = size;
+		self
+	}
+
+	/// How many bytes a [`crate::MmapFile`] reads before yielding to the scheduler, defaults to none.
+	///
+	/// Reads from a mapping are always ready, so a task reading a large file never gives up its worker
+	/// thread on its own. With a budget set, `poll_read` returns `Pending` once after every `budget`
+	/// bytes, waking the task right away so it's rescheduled behind the others. A budget of 0 is the
+	/// same as none.
+	pub fn read_budget(mut self, budget: Option<usize>) -> Self {
+		self.read_budget = budget;
+		self
+	}
+
+	/// How far committed writes are pushed before [`crate::Writer::commit`], [`crate::MappedMut::commit`]
+	/// and [`crate::Syncer::sync`] return, defaults to [`Durability::Flush`].
+	pub fn durability(mut self, durability: Durability) -> Self {
+		self.durability = durability;
+		self
+	}
+
+	/// A check every file has to pass after it's opened and before it's mapped for reading.
+	///
+	/// It runs on the blocking pool with the open file and its metadata, after the built-in checks such
+	/// as [`Config::max_file_size`], and its error fails the open. Services mapping untrusted files can
+	/// use it to check owners or, with `FileExt::read_at`, magic numbers before a single page is mapped.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let config = Config::new().validator(|f, meta| {
+	///     let mut magic = [0; 4];
+	///     f.read_exact_at(&mut magic, 0)?;
+	///     if meta.uid() != UPLOADS_UID || !ALLOWED.contains(&magic) {
+	///         return Err(Error::new(ErrorKind::InvalidData, "rejected upload"));
+	///     }
+	///     Ok(())
+	/// });
+	/// ```
+	pub fn validator(mut self, f: impl Fn(&StdFile, &Metadata) -> Result<()> + Send + Sync + 'static) -> Self {
+		self.validator = Some(Validator(Arc::new(f)));
+		self
+	}
+
+	pub(crate) fn file_policy(&self) -> FilePolicy {
+		FilePolicy {
+			mode: self.file_mode,
+			owner: self.file_owner,
+		}
+	}
+}
+
+/// How durable committed writes are, see [`Config::durability`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+	/// Writes reach the kernel, they survive the process but may be lost if the machine crashes.
+	#[default]
+	Flush,
+	/// The data is synced like `File::sync_data`, metadata that isn't needed to read it back may be lost.
+	Data,
+	/// Data and metadata are synced like `File::sync_all`.
+	Full,
+}
+
+impl Durability {
+	/// Syncs `f`, which must be flushed already, as far as this asks for.
+	pub(crate) async fn sync(self, f: &TokioFile) -> Result<()> {
+		match self {
+			Self::Flush => Ok(()),
+			Self::Data => f.sync_data().await,
+			Self::Full => f.sync_all().await,
+		}
+	}
+}
+
+type ValidateFn = dyn Fn(&StdFile, &Metadata) -> Result<()> + Send + Sync;
+
+/// The check set by [`Config::validator`].
+#[derive(Clone)]
+pub(crate) struct Validator(pub(crate) Arc<ValidateFn>);
+
+impl fmt::Debug for Validator {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Validator")
+	}
+}
+
+/// The permission settings of [`Config`], cheap to move into blocking closures.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FilePolicy {
+	mode: Option<u32>,
+	owner: (Option<u32>, Option<u32>),
+}
+
+impl FilePolicy {
+	/// Returns the policy with `mode`, if set, taking precedence over the configured mode.
+	pub(crate) fn with_mode(mut self, mode: Option<u32>) -> Self {
+		self.mode = mode.or(self.mode);
+		self
+	}
+
+	/// The mode to pass to `open(2)` when creating a file.
+	pub(crate) fn create_mode(&self) -> u32 {
+		self.create_mode_or(0o666)
+	}
+
+	/// The mode to pass to `open(2)` when creating a file, `default` if none is configured.
+	pub(crate) fn create_mode_or(&self, default: u32) -> u32 {
+		self.mode.unwrap_or(default)
+	}
+
+	/// Applies the configured mode and owner to an open file.
+	pub(crate) fn apply(&self, f: &StdFile) -> Result<()> {
+		if let Some(mode) = self.mode {
+			f.set_permissions(Permissions::from_mode(mode))?;
+		}
+		if let (None, None) = self.owner {
+			return Ok(());
+		}
+		fchown(f, self.owner.0, self.owner.1)
+	}
+}
+
+/// Runs `fut`, failing with `ErrorKind::TimedOut` if it takes longer than `timeout`.
+pub(crate) async fn with_timeout<T>(
+	timeout: Option<Duration>,
+	what: &str,
+	fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+	match timeout {
+		Some(t) => tokio::time::timeout(t, fut)
+			.await
+			.map_err(|_| Error::new(ErrorKind::TimedOut, format!("{what} timed out after {t:?}")))?,
+		None => fut.await,
+	}
+}
@@ -0,0 +1,79 @@
+use crate::Result;
+use std::{
+	io::{Error, ErrorKind},
+	time::{Duration, Instant},
+};
+
+/// Per-operation context threaded through [`crate::FileMap`] calls.
+///
+/// Carries the remaining budget of the request an operation is performed for (e.g. an HTTP request's
+/// deadline), so file IO done on its behalf can't outlive it, and an actor id that is reported in
+/// the errors of failed operations.
+///
+/// # Example
+///
+/// ```ignore
+/// let ctx = OpContext::new().with_timeout(Duration::from_millis(250)).with_actor("req-42");
+/// let f = file_map.get_ctx("/path/to/file", &ctx).await?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct OpContext {
+	deadline: Option<Instant>,
+	actor: Option<String>,
+}
+
+impl OpContext {
+	/// Returns a context without deadline or actor.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the instant by which operations must finish.
+	pub fn with_deadline(mut self, deadline: Instant) -> Self {
+		self.deadline = Some(deadline);
+		self
+	}
+
+	/// Sets the deadline to `timeout` from now.
+	pub fn with_timeout(self, timeout: Duration) -> Self {
+		self.with_deadline(Instant::now() + timeout)
+	}
+
+	/// Sets the id of the actor operations are performed for.
+	pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+		self.actor = Some(actor.into());
+		self
+	}
+
+	/// Returns the deadline, if any.
+	pub fn deadline(&self) -> Option<Instant> {
+		self.deadline
+	}
+
+	/// Returns the actor id, if any.
+	pub fn actor(&self) -> Option<&str> {
+		self.actor.as_deref()
+	}
+
+	/// Returns the time left until the deadline, `Some(Duration::ZERO)` once it has passed.
+	pub fn remaining(&self) -> Option<Duration> {
+		self.deadline.map(|d| d.saturating_duration_since(Instant::now()))
+	}
+
+	/// Runs `fut`, failing with `ErrorKind::TimedOut` if the deadline passes first.
+	///
+	/// Errors returned by `fut` are annotated with the actor id, if one is set.
+	pub(crate) async fn run<T>(&self, what: &str, fut: impl Future<Output = Result<T>>) -> Result<T> {
+		let res = match self.deadline {
+			Some(d) => match tokio::time::timeout_at(d.into(), fut).await {
+				Ok(res) => res,
+				Err(_) => Err(Error::new(ErrorKind::TimedOut, format!("{what}: deadline exceeded"))),
+			},
+			None => fut.await,
+		};
+		match &self.actor {
+			Some(actor) => res.map_err(|err| Error::new(err.kind(), format!("{err} (actor {actor})"))),
+			None => res,
+		}
+	}
+}
@@ -0,0 +1,116 @@
+use crate::{MmapFile, Result, mmap_file::seek_pos};
+use std::{
+	io::SeekFrom,
+	pin::Pin,
+	sync::{
+		Arc,
+		atomic::{AtomicU64, Ordering},
+	},
+	task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+/// A reader over a mapped file whose clones all advance one common cursor, see
+/// [`MmapFile::shared_cursor`].
+///
+/// Every read atomically claims the next bytes of the file, so clones handed to different tasks split
+/// one logical stream between them: each byte is read by exactly one of them, in no particular order
+/// across clones. Seeking any clone moves the cursor of all of them.
+#[derive(Clone, Debug)]
+pub struct SharedCursor {
+	f: MmapFile,
+	pos: Arc<AtomicU64>,
+}
+
+impl SharedCursor {
+	pub(crate) fn new(f: MmapFile, pos: u64) -> Self {
+		Self {
+			f,
+			pos: AtomicU64::new(pos).into(),
+		}
+	}
+
+	/// Returns the shared cursor's current position.
+	pub fn position(&self) -> u64 {
+		self.pos.load(Ordering::Acquire)
+	}
+
+	/// Returns the underlying file, whose own cursor is independent of the shared one.
+	pub fn file(&self) -> &MmapFile {
+		&self.f
+	}
+}
+
+impl AsyncRead for SharedCursor {
+	fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		let len = self.f.as_bytes().len() as u64;
+		let want = buf.remaining() as u64;
+		let start = self
+			.pos
+			.fetch_update(Ordering::AcqRel, Ordering::Acquire, |pos| {
+				Some(pos.max(pos.saturating_add(want).min(len)))
+			})
+			.expect("update always succeeds");
+		let n = len.saturating_sub(start).min(want) as usize;
+		let n = self.f.read_at(buf.initialize_unfilled_to(n), start)?;
+		buf.advance(n);
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncSeek for SharedCursor {
+	fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+		let len = self.f.as_bytes().len();
+		let mut err = None;
+		let update = |pos: u64| match seek_pos(len, pos as usize, position) {
+			Ok(pos) => Some(pos as u64),
+			Err(e) => {
+				err = Some(e);
+				None
+			}
+		};
+		match self.pos.fetch_update(Ordering::AcqRel, Ordering::Acquire, update) {
+			Ok(_) => Ok(()),
+			Err(_) => Err(err.expect("seek_pos failed")),
+		}
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
+		Poll::Ready(Ok(self.position()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::{
+		fs::remove_file,
+		io::{AsyncReadExt, AsyncSeekExt},
+	};
+
+	#[tokio::test]
+	async fn test_cursors() -> Result<()> {
+		let path = "/tmp/ammf_cursors";
+		tokio::fs::write(path, b"0123456789").await?;
+		let mut f = MmapFile::open(path).await?;
+		f.set_position(4)?;
+
+		let mut b = [0; 2];
+		f.reader().read_exact(&mut b).await?;
+		assert_eq!((&b, f.position()), (b"01", 4));
+
+		let mut a = f.shared_cursor();
+		let mut c = a.clone();
+		a.read_exact(&mut b).await?;
+		c.read_exact(&mut b).await?;
+		assert_eq!((&b, a.position()), (b"67", 8));
+		a.seek(SeekFrom::Start(1)).await?;
+		c.read_exact(&mut b).await?;
+		assert_eq!(&b, b"12");
+		let mut rest = Vec::new();
+		c.read_to_end(&mut rest).await?;
+		assert_eq!((rest.as_slice(), a.read(&mut b).await?), (&b"3456789"[..], 0));
+		remove_file(path).await?;
+		Ok(())
+	}
+}
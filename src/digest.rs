@@ -0,0 +1,39 @@
+/// A hash function [`crate::MmapFile::digest`] can run over a mapping.
+///
+/// Implement it for any hasher, e.g. one from the `sha2` or `xxhash-rust` crates, to use it with the
+/// crate. CRC32 is provided through `crc32fast`.
+pub trait Digest: Default + Send + 'static {
+	/// The finished hash.
+	type Output: Send + 'static;
+
+	/// Feeds `data` into the hash.
+	fn update(&mut self, data: &[u8]);
+
+	/// Returns the hash of everything fed so far.
+	fn finalize(self) -> Self::Output;
+}
+
+/// A [`Digest`] whose hashes of consecutive chunks can be joined into the hash of their concatenation,
+/// which lets [`crate::MmapFile::digest_parallel`] hash chunks on several threads.
+pub trait CombineDigest: Digest {
+	/// Extends this hash, of the data before, with `next`, the hash of the data following it.
+	fn combine(&mut self, next: &Self);
+}
+
+impl Digest for crc32fast::Hasher {
+	type Output = u32;
+
+	fn update(&mut self, data: &[u8]) {
+		crc32fast::Hasher::update(self, data);
+	}
+
+	fn finalize(self) -> u32 {
+		crc32fast::Hasher::finalize(self)
+	}
+}
+
+impl CombineDigest for crc32fast::Hasher {
+	fn combine(&mut self, next: &Self) {
+		crc32fast::Hasher::combine(self, next);
+	}
+}
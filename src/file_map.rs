@@ -1,25 +1,160 @@
 use std::{
 	collections::HashMap,
+	fs::{File as StdFile, Metadata},
 	io::{Error, ErrorKind},
-	ops::{Deref, DerefMut},
-	sync::Mutex,
+	ops::{ControlFlow, Deref, DerefMut},
+	os::unix::fs::OpenOptionsExt,
+	path::Path,
+	pin::Pin,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicU64, AtomicUsize, Ordering},
+	},
+	task::{Context, Poll, ready},
+	time::Instant,
 };
 
-use tokio::{fs::File, task::yield_now};
+use bytes::{Buf, Bytes};
+use futures::Sink;
+use tokio::{
+	fs::File,
+	io::{AsyncWrite, AsyncWriteExt},
+	sync::{Mutex as AsyncMutex, OwnedSemaphorePermit, Semaphore},
+	task::yield_now,
+};
 
-use crate::{MmapFile, Result};
+use crate::{Config, FsInfo, MmapFile, MmapFileMut, OpContext, OpenOptions, Result, config::with_timeout, root::Root};
 
 const MULTIPLE_READERS: &str = "file is being read";
 const MULTIPLE_WRITERS: &str = "multiple writers aren't allowed";
+const BEING_WRITTEN: &str = "file is being written";
+const REMOVED: &str = "file was removed during iteration";
+const LOCK_TIMEOUT: &str = "timed out waiting for the writer lock";
+const TOO_MANY_WRITERS: &str = "too many writers in flight";
+const TOO_LARGE: &str = "file is larger than requested";
+const NOT_WRITABLE: &str = "writer options must include write or append access";
 
 /// A map of memory-mapped files.
 ///
 /// Only allows one file handle per path.
 ///
+/// # Consistency
+///
+/// A path is either being read or being written, never both: acquiring a [`Writer`] or a writable
+/// mapping with [`FileMap::get_mut`] fails while read-only mappings of the path are handed out, and
+/// `get` fails while either is held. Once a writer has
+/// been [committed](Writer::commit), every later `get` maps the new contents. A writer that is merely
+/// dropped releases the path right away, possibly before tokio has finished its last background
+/// write, so only `commit` gives the read-your-writes guarantee.
 #[derive(Default, Debug)]
 pub struct FileMap {
 	files: Mutex<HashMap<String, MmapFile>>,
 	writers: Mutex<HashMap<String, bool>>,
+	config: Mutex<Arc<Config>>,
+	inflight: Mutex<Inflight>,
+	loading: Mutex<HashMap<String, Arc<LoadSlot>>>,
+	root: Option<Arc<Root>>,
+}
+
+/// The in-flight limits of a [`FileMap`], resized in place when its config is replaced.
+#[derive(Default, Debug)]
+struct Inflight {
+	gets: Option<Arc<Limit>>,
+	writers: Option<Arc<Limit>>,
+}
+
+impl Inflight {
+	fn new(config: &Config) -> Self {
+		let mut inflight = Self::default();
+		inflight.update(config);
+		inflight
+	}
+
+	/// Applies the limits of `config`, keeping the slots taken under the current ones.
+	fn update(&mut self, config: &Config) {
+		Limit::update(&mut self.gets, config.max_inflight_gets);
+		Limit::update(&mut self.writers, config.max_inflight_writers);
+	}
+}
+
+/// One in-flight limit, a semaphore that can shrink below the slots currently taken.
+#[derive(Debug)]
+struct Limit {
+	sem: Arc<Semaphore>,
+	max: AtomicUsize,
+	// slots to retire as they're released, after the limit shrank below the taken ones
+	debt: AtomicUsize,
+}
+
+impl Limit {
+	fn update(limit: &mut Option<Arc<Self>>, max: Option<usize>) {
+		match (limit.as_ref(), max) {
+			(Some(l), Some(max)) => l.resize(max),
+			(_, max) => {
+				*limit = max.map(|n| {
+					Arc::new(Self {
+						sem: Arc::new(Semaphore::new(n)),
+						max: AtomicUsize::new(n),
+						debt: AtomicUsize::new(0),
+					})
+				})
+			}
+		}
+	}
+
+	fn resize(&self, max: usize) {
+		let old = self.max.swap(max, Ordering::AcqRel);
+		if max < old {
+			let forgotten = self.sem.forget_permits(old - max);
+			self.debt.fetch_add(old - max - forgotten, Ordering::AcqRel);
+			return;
+		}
+		// growing pays back the slots still owed first
+		let owed = self
+			.debt
+			.fetch_update(Ordering::AcqRel, Ordering::Acquire, |d| {
+				Some(d.saturating_sub(max - old))
+			})
+			.unwrap();
+		self.sem.add_permits(max - old - owed.min(max - old));
+	}
+
+	fn permit(self: &Arc<Self>, permit: OwnedSemaphorePermit) -> Permit {
+		Permit {
+			permit: Some(permit),
+			limit: self.clone(),
+		}
+	}
+
+	fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+		self.sem.clone().try_acquire_owned().ok().map(|p| self.permit(p))
+	}
+
+	async fn acquire(self: &Arc<Self>) -> Result<Permit> {
+		let p = self.sem.clone().acquire_owned().await.map_err(Error::other)?;
+		Ok(self.permit(p))
+	}
+}
+
+/// A taken slot of a [`Limit`], retired instead of released while the limit owes slots.
+#[derive(Debug)]
+struct Permit {
+	permit: Option<OwnedSemaphorePermit>,
+	limit: Arc<Limit>,
+}
+
+impl Drop for Permit {
+	fn drop(&mut self) {
+		let owed = self
+			.limit
+			.debt
+			.fetch_update(Ordering::AcqRel, Ordering::Acquire, |d| d.checked_sub(1));
+		if owed.is_ok()
+			&& let Some(p) = self.permit.take()
+		{
+			p.forget();
+		}
+	}
 }
 
 impl FileMap {
@@ -29,12 +164,112 @@ impl FileMap {
 	/// # Example
 	///
 	/// ```
+	/// # use async_mmap_file::FileMap;
 	/// let file_map = FileMap::new();
 	/// ```
 	pub fn new() -> Self {
 		Self::default()
 	}
 
+	/// Creates a `FileMap` using the given shared configuration.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use async_mmap_file::{Config, FileMap};
+	/// let file_map = FileMap::with_config(Config::new().populate(false));
+	/// ```
+	pub fn with_config(config: Config) -> Self {
+		Self {
+			inflight: Mutex::new(Inflight::new(&config)),
+			config: Mutex::new(config.into()),
+			..Self::default()
+		}
+	}
+
+	/// Creates a `FileMap` that resolves every path relative to `dir`.
+	///
+	/// The directory is opened once and held as an fd; files are opened with
+	/// `openat2(RESOLVE_BENEATH)`, so absolute paths, `..` and symlinks pointing outside of `dir` are
+	/// rejected with `ErrorKind::PermissionDenied`, and renaming `dir` doesn't affect the map.
+	///
+	/// # Sandboxing
+	///
+	/// `dir` is the only path resolved from the process' view of the filesystem. After construction,
+	/// `get` and the writer methods only use `openat2` (or `openat`) relative to the held fd, plus
+	/// fd-based calls (`fstat`, `ioctl`, `mmap`, and `memfd_create` with [`Config::seal`]). A map can
+	/// therefore be created before applying Landlock or seccomp restrictions that only allow access
+	/// beneath `dir`. Path-based helpers such as [`crate::read`] or `Syncer::sync_path` are not
+	/// covered by this and need their own rules.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if `dir` can't be opened as a directory.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let file_map = FileMap::with_root("/srv/uploads")?;
+	/// let f = file_map.get("user/avatar.png").await?;
+	/// assert!(file_map.get("../etc/passwd").await.is_err());
+	/// ```
+	pub fn with_root(dir: impl AsRef<Path>) -> Result<Self> {
+		Self::with_root_and_config(dir, Config::default())
+	}
+
+	/// Like [`FileMap::with_root`], using the given shared configuration.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if `dir` can't be opened as a directory.
+	pub fn with_root_and_config(dir: impl AsRef<Path>, config: Config) -> Result<Self> {
+		Ok(Self {
+			inflight: Mutex::new(Inflight::new(&config)),
+			config: Mutex::new(config.into()),
+			root: Some(Root::open(dir.as_ref())?.into()),
+			..Self::default()
+		})
+	}
+
+	/// Returns the root directory passed to [`FileMap::with_root`], if any.
+	pub fn root(&self) -> Option<&Path> {
+		self.root.as_deref().map(Root::path)
+	}
+
+	/// Returns a snapshot of the current configuration.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	pub fn config(&self) -> Arc<Config> {
+		self.config.lock().unwrap().clone()
+	}
+
+	/// Replaces the configuration of a live map.
+	///
+	/// Every operation reads the configuration when it starts, so the new settings apply to all later
+	/// calls while operations already in progress finish with the old ones. Mappings that are already
+	/// cached are kept as they are. Changed in-flight limits are resized in place, so gets and writers
+	/// already holding a slot keep counting against the new limit.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use async_mmap_file::{Config, FileMap};
+	/// # use std::time::Duration;
+	/// let file_map = FileMap::new();
+	/// let config = Config::clone(&file_map.config()).lock_timeout(Some(Duration::from_secs(1)));
+	/// file_map.update_config(config);
+	/// ```
+	pub fn update_config(&self, config: Config) {
+		self.inflight.lock().unwrap().update(&config);
+		*self.config.lock().unwrap() = config.into();
+	}
+
 	///
 	/// * `path` - A string slice that holds the path of the file to be retrieved.
 	///
@@ -42,9 +277,15 @@ impl FileMap {
 	///
 	/// * `Result<MmapFile>` - On success, returns the memory-mapped file. On failure, returns an error.
 	///
+	/// Concurrent calls for a path that isn't cached yet open and map it only once: the first one does
+	/// the work while the others wait and then share its mapping. If it fails, the next waiter tries
+	/// again on its own. A mapping made while a writer of the path came and went is discarded and the
+	/// file mapped again, so it can't outlive the writer's commit in the cache.
+	///
 	/// # Errors
 	///
-	/// This function will return an error if the file cannot be opened.
+	/// This function will return an error if the file cannot be opened, or if it is not a regular file
+	/// (see [`MmapFile::open_with_config`]).
 	///
 	/// # Panics
 	///
@@ -57,35 +298,298 @@ impl FileMap {
 	/// let mmap_file = file_map.get("/path/to/file").await?;
 	/// ```
 	pub async fn get(&self, path: &str) -> Result<MmapFile> {
+		self.get_limited(path, None).await
+	}
+
+	/// [`FileMap::get`], failing with `ErrorKind::FileTooLarge` for files longer than `max_len`.
+	///
+	/// The length is checked before a file is mapped, so a file that's too large is neither mapped nor
+	/// cached; a cached one is checked by the length of its mapping.
+	async fn get_limited(&self, path: &str, max_len: Option<u64>) -> Result<MmapFile> {
+		let fits = |f: &MmapFile| match max_len {
+			Some(max) if f.as_bytes().len() as u64 > max => Err(Error::new(ErrorKind::FileTooLarge, TOO_LARGE)),
+			_ => Ok(f.clone()),
+		};
 		let path = path.to_owned();
-		{
-			let m = self.writers.lock().unwrap();
-			if m.contains_key(&path) {
-				return Err(Error::new(ErrorKind::Other, "file is being written"));
+		if self.writers.lock().unwrap().contains_key(&path) {
+			return Err(Error::other(BEING_WRITTEN));
+		}
+		if let Some(f) = self.files.lock().unwrap().get(&path) {
+			return fits(f);
+		}
+
+		// concurrent misses for the same path queue up behind the first one, which maps the file once
+		// for all of them; the others find it cached when they get their turn
+		let slot = Loading::new(self, &path);
+		let _loading = slot.slot.lock.lock().await;
+		if let Some(f) = self.files.lock().unwrap().get(&path) {
+			return fits(f);
+		}
+
+		let gets = self.inflight.lock().unwrap().gets.clone();
+		let _permit = match gets {
+			Some(limit) => Some(limit.acquire().await?),
+			None => None,
+		};
+		loop {
+			let writes = {
+				let wm = self.writers.lock().unwrap();
+				if wm.contains_key(&path) {
+					return Err(Error::other(BEING_WRITTEN));
+				}
+				if let Some(f) = self.files.lock().unwrap().get(&path) {
+					return fits(f);
+				}
+				slot.slot.writes.load(Ordering::Acquire)
+			};
+			let f = self.map(&path, max_len).await?;
+			let wm = self.writers.lock().unwrap();
+			if wm.contains_key(&path) {
+				return Err(Error::other(BEING_WRITTEN));
 			}
+			// a writer came and went while the file was being mapped, so the mapping may predate its commit
+			if slot.slot.writes.load(Ordering::Acquire) != writes {
+				continue;
+			}
+			let mut m = self.files.lock().unwrap();
+			return fits(m.entry(path.clone()).or_insert(f));
 		}
-		{
-			let m = self.writers.lock().unwrap();
-			if m.contains_key(&path) {
-				return Err(Error::new(ErrorKind::Other, "file is being written"));
+	}
+
+	/// Returns the contents of `path` as zero-copy `Bytes` backed by its cached mapping.
+	///
+	/// A shorthand for [`FileMap::get`] followed by [`MmapFile::into_bytes`], for small hot files. The
+	/// returned `Bytes` count as readers of the path, so writers can't be acquired while they're alive.
+	///
+	/// # Arguments
+	///
+	/// * `path` - A string slice that holds the path of the file to be retrieved.
+	/// * `max_len` - The largest file returned, so big files aren't pinned in memory by accident.
+	///
+	/// # Errors
+	///
+	/// Besides the errors of `get`, this returns an `ErrorKind::FileTooLarge` error if the file is
+	/// longer than `max_len`. The length is checked before the file is mapped, so such a file is
+	/// neither mapped nor cached.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let file_map = FileMap::new();
+	/// let body = file_map.get_bytes("/path/to/file", 64 << 10).await?;
+	/// ```
+	pub async fn get_bytes(&self, path: &str, max_len: usize) -> Result<Bytes> {
+		let max_len = u64::try_from(max_len).unwrap_or(u64::MAX);
+		Ok(self.get_limited(path, Some(max_len)).await?.into_bytes())
+	}
+
+	/// Maps `path`, beneath the root if there is one, without touching the cache.
+	///
+	/// `max_len` tightens the config's `max_file_size` for this mapping only.
+	async fn map(&self, path: &str, max_len: Option<u64>) -> Result<MmapFile> {
+		let mut config = self.config();
+		if let Some(max) = max_len.filter(|&max| config.max_file_size.is_none_or(|limit| max < limit)) {
+			config = Config::clone(&config).max_file_size(Some(max)).into();
+		}
+		match &self.root {
+			Some(root) => {
+				let (root, p) = (root.clone(), path.to_owned());
+				let full = root.path().join(path);
+				MmapFile::map_with(
+					&full,
+					move || root.open_beneath(&p, libc::O_RDONLY | libc::O_NONBLOCK, 0),
+					&config,
+				)
+				.await
 			}
+			None => MmapFile::open_with_config(path, &config).await,
+		}
+	}
+
+	/// Reads the metadata of `path` on the blocking pool, beneath the root if there is one.
+	async fn stat(&self, path: &str) -> Result<Metadata> {
+		let config = self.config();
+		let (root, p) = (self.root.clone(), path.to_owned());
+		let stat = config.blocking_pool.spawn(move || match root {
+			Some(root) => root.open_beneath(&p, libc::O_PATH, 0)?.metadata(),
+			None => std::fs::metadata(p),
+		});
+		with_timeout(config.io_timeout, "stat", stat).await
+	}
+
+	/// Returns the file system `path` lives on, beneath the root if there is one, see [`crate::fs_info`].
+	///
+	/// # Errors
+	///
+	/// This function returns the errors of opening `path`.
+	pub async fn fs_info(&self, path: &str) -> Result<FsInfo> {
+		let config = self.config();
+		let (root, p) = (self.root.clone(), path.to_owned());
+		let info = config.blocking_pool.spawn(move || match root {
+			Some(root) => FsInfo::of(&root.open_beneath(&p, libc::O_PATH, 0)?),
+			None => FsInfo::of(&StdFile::open(p)?),
+		});
+		with_timeout(config.io_timeout, "statfs", info).await
+	}
+
+	/// Returns whether `path` exists, without opening or mapping it.
+	///
+	/// Cached and currently written paths are answered from memory; anything else costs one `stat` on
+	/// the blocking pool, beneath the root if there is one. A cached entry whose file was deleted since
+	/// still counts as present until it's removed or [refreshed](FileMap::refresh_stale).
+	///
+	/// # Errors
+	///
+	/// This function returns stat errors other than `NotFound`, e.g. `PermissionDenied`.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let file_map = FileMap::new();
+	/// if !file_map.exists("/path/to/file").await? {
+	///     return not_found();
+	/// }
+	/// ```
+	pub async fn exists(&self, path: &str) -> Result<bool> {
+		if self.writers.lock().unwrap().contains_key(path) || self.files.lock().unwrap().contains_key(path) {
+			return Ok(true);
+		}
+		match self.stat(path).await {
+			Ok(_) => Ok(true),
+			Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+			Err(err) => Err(err),
+		}
+	}
+
+	/// Remaps cached files that changed on disk since they were mapped, swapping in the fresh mappings.
+	///
+	/// A file counts as changed if its path now names a different file, or if its size or modification
+	/// time differ. Until the fresh mapping is ready `get` keeps handing out the old one, so running this
+	/// periodically in the background keeps hot paths from ever paying for the reopen. Files that are no
+	/// longer there are dropped from the cache, and paths with a writer are skipped.
+	///
+	/// # Returns
+	///
+	/// The number of refreshed or dropped entries.
+	///
+	/// # Errors
+	///
+	/// This function returns the first error, other than `NotFound`, encountered while checking or
+	/// remapping a file. Entries processed before it stay refreshed.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let file_map = Arc::new(FileMap::new());
+	/// let fm = file_map.clone();
+	/// tokio::spawn(async move {
+	///     let mut every = tokio::time::interval(Duration::from_secs(5));
+	///     loop {
+	///         every.tick().await;
+	///         let _ = fm.refresh_stale().await;
+	///     }
+	/// });
+	/// ```
+	pub async fn refresh_stale(&self) -> Result<usize> {
+		let paths: Vec<String> = self.files.lock().unwrap().keys().cloned().collect();
+		let mut refreshed = 0;
+		for path in paths {
+			refreshed += usize::from(self.reopen_if_changed(&path).await?);
+		}
+		Ok(refreshed)
+	}
+
+	/// Returns whether the cached mapping of `path` no longer matches the file on disk.
+	///
+	/// Like [`FileMap::refresh_stale`], this compares the identity, size and modification time the file
+	/// was mapped with against a fresh `stat`. Paths that aren't cached are never stale, cached ones
+	/// whose file is gone always are.
+	///
+	/// # Errors
+	///
+	/// This function returns stat errors other than `NotFound`.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	pub async fn is_stale(&self, path: &str) -> Result<bool> {
+		let Some(old) = self.files.lock().unwrap().get(path).cloned() else {
+			return Ok(false);
+		};
+		match self.stat(path).await {
+			Ok(meta) => Ok(old.is_stale(&meta)),
+			Err(err) if err.kind() == ErrorKind::NotFound => Ok(true),
+			Err(err) => Err(err),
 		}
+	}
+
+	/// Remaps the cached file at `path` if it changed on disk, or drops it if it's gone.
+	///
+	/// This is [`FileMap::refresh_stale`] for a single path, handles from earlier `get`s keep the old
+	/// mapping.
+	///
+	/// # Returns
+	///
+	/// Whether the entry was refreshed or dropped.
+	///
+	/// # Errors
+	///
+	/// This function returns errors, other than `NotFound`, encountered while checking or remapping the
+	/// file.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// if file_map.reopen_if_changed("/etc/app/rules").await? {
+	///     rules = parse(&file_map.get("/etc/app/rules").await?)?;
+	/// }
+	/// ```
+	pub async fn reopen_if_changed(&self, path: &str) -> Result<bool> {
+		let Some(old) = self.files.lock().unwrap().get(path).cloned() else {
+			return Ok(false);
+		};
+		let fresh = match self.stat(path).await {
+			Ok(meta) if !old.is_stale(&meta) => return Ok(false),
+			Ok(_) => match self.map(path, None).await {
+				Ok(f) => Some(f),
+				Err(err) if err.kind() == ErrorKind::NotFound => None,
+				Err(err) => return Err(err),
+			},
+			Err(err) if err.kind() == ErrorKind::NotFound => None,
+			Err(err) => return Err(err),
+		};
+
+		// only swap if nobody replaced or removed the entry (or took a writer) in the meantime
+		let wm = self.writers.lock().unwrap();
 		let mut m = self.files.lock().unwrap();
-		match m.get(&path) {
-			Some(f) => Ok(f.clone()),
-			None => {
-				let f = MmapFile::open(&path).await?;
-				m.insert(path, f.clone());
-				Ok(f)
-			}
+		if wm.contains_key(path) || !m.get(path).is_some_and(|cur| cur.same_mapping(&old)) {
+			return Ok(false);
 		}
+		match fresh {
+			Some(f) => m.insert(path.to_owned(), f),
+			None => m.remove(path),
+		};
+		Ok(true)
 	}
 
 	/// Attempts to acquire a writer for the specified file path.
 	///
 	/// This method will continuously try to acquire a writer for the file at the given path.
 	/// If the file is currently being written by another writer, it will yield and retry until
-	/// it succeeds or encounters an error other than `ErrorKind::Other`.
+	/// it succeeds or encounters an error other than `ErrorKind::Other`. If `Config::max_inflight_writers`
+	/// writers are already open, it waits for one of them to be dropped.
 	///
 	/// # Arguments
 	///
@@ -99,7 +603,8 @@ impl FileMap {
 	/// # Errors
 	///
 	/// This function will return an error if it fails to acquire a writer for reasons other than
-	/// the file being currently written by another writer.
+	/// the file being currently written by another writer, or an `ErrorKind::TimedOut` error if
+	/// `Config::lock_timeout` elapses first.
 	///
 	/// # Example
 	///
@@ -112,10 +617,49 @@ impl FileMap {
 	///
 	/// This function will panic if the mutex is poisoned.
 	pub async fn writer(&self, path: &str, append: bool) -> Result<Writer<'_>> {
+		self.writer_with(path, &writer_options(append)).await
+	}
+
+	/// Like [`FileMap::writer`], opening the file with `opts` instead of write + create.
+	///
+	/// This allows truncating, must-not-exist (`create_new`) or explicit-mode semantics, e.g. for
+	/// append-only logs that must never be overwritten. `read` is honored, so it has to be turned off
+	/// explicitly for write-only access. The mapping settings of `opts` are ignored, the map's own
+	/// [`Config`] applies.
+	///
+	/// # Errors
+	///
+	/// Besides the errors of `writer`, this returns an `ErrorKind::InvalidInput` error if `opts` has
+	/// neither `write` nor `append` set, or the errors of opening the file, e.g. `AlreadyExists` for
+	/// `create_new`.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let opts = OpenOptions::new().read(false).write(true).create_new(true).mode(0o600);
+	/// let writer = file_map.writer_with("/path/to/file", &opts).await?;
+	/// ```
+	pub async fn writer_with(&self, path: &str, opts: &OpenOptions) -> Result<Writer<'_>> {
+		if !opts.writes() {
+			return Err(Error::new(ErrorKind::InvalidInput, NOT_WRITABLE));
+		}
+		let deadline = self.config().lock_timeout.map(|t| Instant::now() + t);
+		// waits for a free writer slot instead of spinning on it, the path's lock is retried below
+		let mut permit = self.writer_permit(deadline).await?;
 		loop {
-			match self.try_writer(path, append).await {
-				Ok(w) => return Ok(w),
+			match self.lock_for_write(path, &mut permit) {
+				Ok(lock) => {
+					let f = self.open_writer(&lock.path, opts).await?;
+					return Ok(Writer { lock, f });
+				}
 				Err(err) if err.kind() == ErrorKind::Other => {
+					if deadline.is_some_and(|d| Instant::now() >= d) {
+						return Err(Error::new(ErrorKind::TimedOut, LOCK_TIMEOUT));
+					}
 					yield_now().await;
 				}
 				Err(err) => return Err(err),
@@ -124,16 +668,52 @@ impl FileMap {
 	}
 
 	pub async fn try_writer(&self, path: &str, append: bool) -> Result<Writer<'_>> {
+		self.try_writer_with(path, &writer_options(append)).await
+	}
+
+	async fn try_writer_with(&self, path: &str, opts: &OpenOptions) -> Result<Writer<'_>> {
+		// released on error, or if this future is dropped mid-open
+		let lock = self.lock_for_write(path, &mut self.try_writer_permit()?)?;
+		let f = self.open_writer(&lock.path, opts).await?;
+		Ok(Writer { lock, f })
+	}
+
+	/// Takes a slot of `Config::max_inflight_writers`, if set, failing if none is free.
+	fn try_writer_permit(&self) -> Result<Option<Permit>> {
+		let writers = self.inflight.lock().unwrap().writers.clone();
+		writers
+			.map(|limit| limit.try_acquire().ok_or_else(|| Error::other(TOO_MANY_WRITERS)))
+			.transpose()
+	}
+
+	/// Waits for a slot of `Config::max_inflight_writers`, if set, until `deadline`.
+	async fn writer_permit(&self, deadline: Option<Instant>) -> Result<Option<Permit>> {
+		let Some(limit) = self.inflight.lock().unwrap().writers.clone() else {
+			return Ok(None);
+		};
+		let permit = match deadline {
+			Some(d) => tokio::time::timeout_at(d.into(), limit.acquire())
+				.await
+				.map_err(|_| Error::new(ErrorKind::TimedOut, LOCK_TIMEOUT))?,
+			None => limit.acquire().await,
+		};
+		permit.map(Some)
+	}
+
+	/// Takes the writer lock of `path`, dropping its cached mapping if nobody else holds it.
+	///
+	/// The lock keeps `permit` only if it's taken, so a caller can retry with the same permit.
+	fn lock_for_write(&self, path: &str, permit: &mut Option<Permit>) -> Result<WriteLock<'_>> {
 		let path = path.to_owned();
 		{
 			let mut wm = self.writers.lock().unwrap();
 			match wm.get(&path) {
-				Some(_) => return Err(Error::new(ErrorKind::Other, MULTIPLE_WRITERS)),
+				Some(_) => return Err(Error::other(MULTIPLE_WRITERS)),
 				None => {
 					let mut fm = self.files.lock().unwrap();
 					match fm.get(&path) {
 						Some(f) if f.reader_count() > 1 => {
-							return Err(Error::new(ErrorKind::Other, MULTIPLE_READERS));
+							return Err(Error::other(MULTIPLE_READERS));
 						}
 						Some(_) => {
 							fm.remove(&path);
@@ -142,32 +722,33 @@ impl FileMap {
 					}
 
 					wm.insert(path.clone(), true);
+					// a `get` mapping the path right now must not cache what it mapped
+					if let Some(slot) = self.loading.lock().unwrap().get(&path) {
+						slot.writes.fetch_add(1, Ordering::AcqRel);
+					}
 				}
 			}
 		}
 
-		let f = File::options()
-			.write(true)
-			.append(append)
-			.create(true)
-			.open(&path)
-			.await;
-
-		match f {
-			Ok(f) => Ok(Writer { fm: self, path, f }),
-			Err(err) => {
-				let mut wm = self.writers.lock().unwrap();
-				wm.remove(&path);
-				Err(err)
-			}
-		}
+		Ok(WriteLock {
+			fm: self,
+			path,
+			_permit: permit.take(),
+		})
 	}
 
+	/// Maps `path` for reading and writing, holding it like a [`Writer`] until the mapping is dropped.
 	///
-	/// * `path` - A string slice that holds the path of the file to be deleted.
+	/// This is the writable counterpart of [`FileMap::get`]; a path is mapped in one mode at a time.
+	/// Taking a writable mapping drops the cached read-only one, if nobody else holds it, and fails
+	/// otherwise, while `get` fails as long as the writable mapping is alive.
+	/// [`MappedMut::downgrade`] turns it back into a cached read-only mapping without letting a writer
+	/// slip in between. The file must exist, it's opened with the map's [`Config`].
 	///
-	/// This method will remove the file associated with the given path from the map.
-	/// If the file does not exist in the map, the method will do nothing.
+	/// # Errors
+	///
+	/// This function will return an `ErrorKind::Other` error if the path is being read or written, and
+	/// the errors of opening and mapping the file.
 	///
 	/// # Panics
 	///
@@ -176,37 +757,405 @@ impl FileMap {
 	/// # Example
 	///
 	/// ```ignore
-	/// let file_map = FileMap::new();
-	/// let mmap_file = file_map.get("/path/to/file").await?;
-	/// file_map.remove("/path/to/file");
+	/// let mut index = file_map.get_mut("/path/to/index").await?;
+	/// index.seek(SeekFrom::Start(slot)).await?;
+	/// index.write_all(&entry).await?;
+	/// let index = index.downgrade().await?; // shared with every later `get`
 	/// ```
-	pub fn remove(&self, path: &str) {
-		let mut m = self.files.lock().unwrap();
-		m.remove(path);
+	pub async fn get_mut(&self, path: &str) -> Result<MappedMut<'_>> {
+		let lock = self.lock_for_write(path, &mut self.try_writer_permit()?)?;
+		let config = self.config();
+		let open = self.opener(path, &OpenOptions::new().write(true), &config);
+		let f = MmapFileMut::map_with(open, &config).await?;
+		Ok(MappedMut { lock, f })
 	}
 
-	pub async fn remove_blocking(&self, path: &str) -> Option<MmapFile> {
-		let f = {
-			let mut m = self.files.lock().unwrap();
-			m.remove(path)
-		};
+	/// Like [`FileMap::get`], bounded by the deadline of `ctx`.
+	///
+	/// # Errors
+	///
+	/// Besides the errors of `get`, this returns an `ErrorKind::TimedOut` error if the deadline passes
+	/// before the file is mapped. Errors mention the actor of `ctx`, if set.
+	pub async fn get_ctx(&self, path: &str, ctx: &OpContext) -> Result<MmapFile> {
+		ctx.run("get", self.get(path)).await
+	}
+
+	/// Like [`FileMap::writer`], bounded by the deadline of `ctx`.
+	///
+	/// Waiting for the writer lock and opening the file both count against the deadline.
+	///
+	/// # Errors
+	///
+	/// Besides the errors of `writer`, this returns an `ErrorKind::TimedOut` error if the deadline
+	/// passes first. Errors mention the actor of `ctx`, if set.
+	pub async fn writer_ctx(&self, path: &str, append: bool, ctx: &OpContext) -> Result<Writer<'_>> {
+		ctx.run("writer", self.writer(path, append)).await
+	}
+
+	/// Acquires a writer for the specified file path and wraps it in a `Sink<Bytes>`.
+	///
+	/// This is a convenience over [`FileMap::writer`] for forwarding data from channels or sockets
+	/// into a file with backpressure: each item is fully written before the sink accepts the next one.
+	///
+	/// # Arguments
+	///
+	/// * `path` - A string slice that holds the path of the file to be written.
+	/// * `append` - A boolean indicating whether to append to the file if it exists.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if it fails to acquire a writer, see [`FileMap::writer`].
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let file_map = FileMap::new();
+	/// let mut sink = file_map.put_sink("/path/to/file", false).await?;
+	/// rx.map(Ok).forward(&mut sink).await?;
+	/// sink.close().await?;
+	/// ```
+	pub async fn put_sink(&self, path: &str, append: bool) -> Result<PutSink<'_>> {
+		let w = self.writer(path, append).await?;
+		Ok(PutSink { w, buf: Bytes::new() })
+	}
+
+	async fn open_writer(&self, path: &str, opts: &OpenOptions) -> Result<File> {
+		let config = self.config();
+		let open = config.blocking_pool.spawn(self.opener(path, opts, &config));
+		with_timeout(config.io_timeout, "open", open).await.map(File::from_std)
+	}
+
+	/// Returns a blocking call that opens `path` for writing with `opts`, beneath the root if there is one.
+	fn opener(
+		&self,
+		path: &str,
+		opts: &OpenOptions,
+		config: &Config,
+	) -> impl FnOnce() -> Result<StdFile> + Send + 'static {
+		let policy = config.file_policy().with_mode(opts.create_mode());
+		let (root, path, flags) = (self.root.clone(), path.to_owned(), opts.flags());
+		let mut std_opts = opts.std_options(0);
+		std_opts.mode(policy.create_mode());
+		move || {
+			let f = match root {
+				Some(root) => root.open_beneath(&path, flags, policy.create_mode())?,
+				None => std_opts.open(&path)?,
+			};
+			policy.apply(&f)?;
+			Ok(f)
+		}
+	}
+
+	///
+	/// * `path` - A string slice that holds the path of the file to be deleted.
+	///
+	/// This method will remove the file associated with the given path from the map.
+	/// If the file does not exist in the map, the method will do nothing.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let file_map = FileMap::new();
+	/// let mmap_file = file_map.get("/path/to/file").await?;
+	/// file_map.remove("/path/to/file");
+	/// ```
+	pub fn remove(&self, path: &str) {
+		let mut m = self.files.lock().unwrap();
+		m.remove(path);
+	}
+
+	pub async fn remove_blocking(&self, path: &str) -> Option<MmapFile> {
+		let f = {
+			let mut m = self.files.lock().unwrap();
+			m.remove(path)
+		};
 
 		match f {
 			Some(f) => {
 				while f.reader_count() > 1 {
 					yield_now().await;
 				}
-				return Some(f);
+				Some(f)
 			}
 			None => None,
 		}
 	}
+
+	/// Calls `f` for every file currently in the map.
+	///
+	/// Iteration runs over a snapshot of the paths taken when the call starts. Each entry is looked up
+	/// under a short-lived lock and the lock is released before `f` runs, so writers and `get` calls on
+	/// other paths are never blocked by a slow callback.
+	///
+	/// Paths added after the snapshot are not visited. Paths removed after the snapshot are skipped,
+	/// unless `strict` is set, in which case iteration stops with an `ErrorKind::NotFound` error.
+	///
+	/// # Arguments
+	///
+	/// * `strict` - Whether a path removed mid-iteration is an error instead of being skipped.
+	/// * `f` - An async callback receiving the path and a clone of the memory-mapped file.
+	///
+	/// # Errors
+	///
+	/// This function returns the first error returned by `f`, or a `NotFound` error in strict mode.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let file_map = FileMap::new();
+	/// file_map.for_each(false, async |path, f| {
+	///     println!("{path}: {} bytes", f.metadata().await?.len());
+	///     Ok(())
+	/// }).await?;
+	/// ```
+	pub async fn for_each<F>(&self, strict: bool, mut f: F) -> Result<()>
+	where
+		F: AsyncFnMut(String, MmapFile) -> Result<()>,
+	{
+		self.for_each_until(strict, async |path, file| {
+			f(path, file).await.map(ControlFlow::Continue)
+		})
+		.await
+		.map(drop)
+	}
+
+	/// Like [`FileMap::for_each`], but `f` can stop the iteration early by returning `ControlFlow::Break`.
+	///
+	/// # Returns
+	///
+	/// `ControlFlow::Break` if `f` stopped the iteration, `ControlFlow::Continue` if every entry was visited.
+	///
+	/// # Errors
+	///
+	/// This function returns the first error returned by `f`, or a `NotFound` error in strict mode.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut big = None;
+	/// file_map.for_each_until(false, async |path, f| {
+	///     if f.metadata().await?.len() > 1 << 30 {
+	///         big = Some(path);
+	///         return Ok(ControlFlow::Break(()));
+	///     }
+	///     Ok(ControlFlow::Continue(()))
+	/// }).await?;
+	/// ```
+	pub async fn for_each_until<F>(&self, strict: bool, mut f: F) -> Result<ControlFlow<()>>
+	where
+		F: AsyncFnMut(String, MmapFile) -> Result<ControlFlow<()>>,
+	{
+		let paths: Vec<String> = self.files.lock().unwrap().keys().cloned().collect();
+		for path in paths {
+			let file = self.files.lock().unwrap().get(&path).cloned();
+			match file {
+				Some(file) => {
+					if f(path, file).await?.is_break() {
+						return Ok(ControlFlow::Break(()));
+					}
+				}
+				None if strict => return Err(Error::new(ErrorKind::NotFound, REMOVED)),
+				None => {}
+			}
+		}
+		Ok(ControlFlow::Continue(()))
+	}
+
+	/// Like [`FileMap::for_each`], but keeps going when `f` fails for an entry.
+	///
+	/// Meant for bulk jobs that shouldn't abort on the first bad file. Removed paths are skipped.
+	///
+	/// # Returns
+	///
+	/// The path and error of every failed call to `f`, in iteration order.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let failed = file_map.for_each_all(async |path, f| verify(&f).await).await;
+	/// for (path, err) in failed {
+	///     eprintln!("{path}: {err}");
+	/// }
+	/// ```
+	pub async fn for_each_all<F>(&self, mut f: F) -> Vec<(String, Error)>
+	where
+		F: AsyncFnMut(String, MmapFile) -> Result<()>,
+	{
+		let mut failed = vec![];
+		let _ = self
+			.for_each_until(false, async |path, file| {
+				if let Err(err) = f(path.clone(), file).await {
+					failed.push((path, err));
+				}
+				Ok(ControlFlow::Continue(()))
+			})
+			.await;
+		failed
+	}
 }
 
+/// The options `writer` and `try_writer` open files with.
+fn writer_options(append: bool) -> OpenOptions {
+	OpenOptions::new().read(false).write(true).create(true).append(append)
+}
+
+/// Exclusive write access to a path of a [`FileMap`], released on drop.
 pub struct Writer<'a> {
+	lock: WriteLock<'a>,
+	f: File,
+}
+
+/// Removes a path from the writers of a [`FileMap`] when dropped.
+struct WriteLock<'a> {
 	fm: &'a FileMap,
 	path: String,
-	f: File,
+	_permit: Option<Permit>,
+}
+
+/// The state shared by the `get` calls missing the cache for one path.
+#[derive(Default, Debug)]
+struct LoadSlot {
+	/// Serializes the misses, so only the first one maps the file.
+	lock: AsyncMutex<()>,
+	/// Bumped by every writer acquired for the path while the slot exists.
+	writes: AtomicU64,
+}
+
+/// A caller's claim on the [`LoadSlot`] of a path.
+///
+/// The slot is removed from the map once its last claim is dropped, including when a `get` fails or
+/// is cancelled.
+struct Loading<'a> {
+	fm: &'a FileMap,
+	path: &'a str,
+	slot: Arc<LoadSlot>,
+}
+
+impl<'a> Loading<'a> {
+	fn new(fm: &'a FileMap, path: &'a str) -> Self {
+		let slot = fm.loading.lock().unwrap().entry(path.to_owned()).or_default().clone();
+		Self { fm, path, slot }
+	}
+}
+
+impl Drop for Loading<'_> {
+	fn drop(&mut self) {
+		let mut m = self.fm.loading.lock().unwrap();
+		// one reference is held by the map, claims are only taken while holding its mutex
+		if Arc::strong_count(&self.slot) == 2 {
+			m.remove(self.path);
+		}
+	}
+}
+
+impl Drop for WriteLock<'_> {
+	fn drop(&mut self) {
+		self.fm.writers.lock().unwrap().remove(&self.path);
+	}
+}
+
+impl Writer<'_> {
+	/// Finishes writing and releases the path.
+	///
+	/// Waits for all buffered and in-flight writes to reach the file, then invalidates any cached
+	/// mapping of the path before releasing the writer lock, so a `get` issued after this returns is
+	/// guaranteed to observe the new contents. With [`Config::durability`] set, the file is synced
+	/// before the path is released, too.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if flushing or syncing the file fails; the path is released
+	/// either way.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut w = file_map.writer("/path/to/file", false).await?;
+	/// w.write_all(b"new contents").await?;
+	/// w.commit().await?;
+	/// let f = file_map.get("/path/to/file").await?; // sees "new contents"
+	/// ```
+	pub async fn commit(mut self) -> Result<()> {
+		self.f.flush().await?;
+		self.lock.fm.config().durability.sync(&self.f).await?;
+		self.lock.fm.files.lock().unwrap().remove(&self.lock.path);
+		Ok(())
+	}
+}
+
+/// A writable mapping of a path of a [`FileMap`], see [`FileMap::get_mut`].
+///
+/// The path stays locked against readers and writers until this is dropped or downgraded.
+pub struct MappedMut<'a> {
+	lock: WriteLock<'a>,
+	f: MmapFileMut,
+}
+
+impl MappedMut<'_> {
+	/// Flushes the writes and releases the path, like [`Writer::commit`].
+	///
+	/// Flushing waits for `msync(2)`, so the data is durable even with [`crate::Durability::Flush`];
+	/// [`crate::Durability::Full`] syncs the metadata too.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if flushing or syncing fails; the path is released either way.
+	pub async fn commit(mut self) -> Result<()> {
+		self.f.flush().await?;
+		self.lock.fm.config().durability.sync(self.f.as_file()).await
+	}
+
+	/// Flushes the writes and swaps this for a read-only mapping of the path, which is cached for later
+	/// `get` calls.
+	///
+	/// The path is released only once the read-only mapping is cached, so no writer can change the file
+	/// in between.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if flushing or mapping the file fails; the path is released
+	/// either way.
+	///
+	/// # Panics
+	///
+	/// This function will panic if the mutex is poisoned.
+	pub async fn downgrade(mut self) -> Result<MmapFile> {
+		self.f.flush().await?;
+		let fm = self.lock.fm;
+		let f = fm.map(&self.lock.path, None).await?;
+		fm.files.lock().unwrap().insert(self.lock.path.clone(), f.clone());
+		Ok(f)
+	}
+}
+
+impl Deref for MappedMut<'_> {
+	type Target = MmapFileMut;
+
+	fn deref(&self) -> &Self::Target {
+		&self.f
+	}
+}
+
+impl DerefMut for MappedMut<'_> {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.f
+	}
 }
 
 impl Deref for Writer<'_> {
@@ -223,16 +1172,58 @@ impl DerefMut for Writer<'_> {
 	}
 }
 
-impl Drop for Writer<'_> {
-	fn drop(&mut self) {
-		self.fm.writers.lock().unwrap().remove(&self.path);
+/// A `Sink<Bytes>` writing into a file held by a [`Writer`], see [`FileMap::put_sink`].
+///
+/// The writer is released when the sink is dropped; call `close` first to make sure all data was written.
+pub struct PutSink<'a> {
+	w: Writer<'a>,
+	buf: Bytes,
+}
+
+impl PutSink<'_> {
+	fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		while !self.buf.is_empty() {
+			let n = ready!(Pin::new(&mut *self.w).poll_write(cx, &self.buf))?;
+			if n == 0 {
+				return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+			}
+			self.buf.advance(n);
+		}
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl Sink<Bytes> for PutSink<'_> {
+	type Error = Error;
+
+	fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		self.get_mut().poll_drain(cx)
+	}
+
+	fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<()> {
+		self.get_mut().buf = item;
+		Ok(())
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		let this = self.get_mut();
+		ready!(this.poll_drain(cx))?;
+		Pin::new(&mut *this.w).poll_flush(cx)
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		let this = self.get_mut();
+		ready!(this.poll_drain(cx))?;
+		Pin::new(&mut *this.w).poll_shutdown(cx)
 	}
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use tokio::fs::remove_file;
+	use futures::SinkExt;
+	use std::time::Duration;
+	use tokio::{fs::remove_file, io::AsyncReadExt};
 
 	#[tokio::test]
 	async fn test_file_map() {
@@ -247,4 +1238,491 @@ mod tests {
 		file_map.get("/tmp/y").await.expect("reader failed");
 		remove_file("/tmp/y").await.expect("delete failed");
 	}
+
+	#[tokio::test]
+	async fn test_for_each() {
+		let file_map = FileMap::new();
+		for path in ["/tmp/ammf_each_a", "/tmp/ammf_each_b"] {
+			drop(file_map.try_writer(path, false).await.expect("writer failed"));
+			file_map.get(path).await.expect("reader failed");
+		}
+
+		let mut seen = vec![];
+		file_map
+			.for_each(false, async |path, _| {
+				file_map.remove("/tmp/ammf_each_a");
+				file_map.remove("/tmp/ammf_each_b");
+				seen.push(path);
+				Ok(())
+			})
+			.await
+			.expect("for_each failed");
+		assert_eq!(seen.len(), 1);
+
+		for path in ["/tmp/ammf_each_a", "/tmp/ammf_each_b"] {
+			file_map.get(path).await.expect("reader failed");
+		}
+		let res = file_map
+			.for_each(true, async |_, _| {
+				file_map.remove("/tmp/ammf_each_a");
+				file_map.remove("/tmp/ammf_each_b");
+				Ok(())
+			})
+			.await;
+		assert_eq!(res.expect_err("strict should fail").kind(), ErrorKind::NotFound);
+
+		for path in ["/tmp/ammf_each_a", "/tmp/ammf_each_b"] {
+			remove_file(path).await.expect("delete failed");
+		}
+	}
+
+	#[tokio::test]
+	async fn test_put_sink() {
+		let path = "/tmp/ammf_sink";
+		let file_map = FileMap::new();
+		let mut sink = file_map.put_sink(path, false).await.expect("sink failed");
+		sink.send(Bytes::from_static(b"hello ")).await.expect("send failed");
+		sink.send(Bytes::from_static(b"world")).await.expect("send failed");
+		sink.close().await.expect("close failed");
+		drop(sink);
+
+		let mut buf = String::new();
+		let mut f = file_map.get(path).await.expect("reader failed");
+		f.read_to_string(&mut buf).await.expect("read failed");
+		assert_eq!(buf, "hello world");
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_config() {
+		let path = "/tmp/ammf_config";
+		let config = Config::new()
+			.max_file_size(Some(4))
+			.lock_timeout(Some(Duration::from_millis(10)));
+		let file_map = FileMap::with_config(config);
+		let w = file_map.writer(path, false).await.expect("writer failed");
+		let err = file_map
+			.writer(path, false)
+			.await
+			.err()
+			.expect("writer should time out");
+		assert_eq!(err.kind(), ErrorKind::TimedOut);
+		drop(w);
+
+		tokio::fs::write(path, b"too large").await.expect("write failed");
+		let err = file_map.get(path).await.expect_err("get should fail");
+		assert_eq!(err.kind(), ErrorKind::FileTooLarge);
+
+		file_map.update_config(Config::clone(&file_map.config()).max_file_size(None));
+		file_map.get(path).await.expect("reader failed");
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_root() {
+		let (dir, moved) = ("/tmp/ammf_root", "/tmp/ammf_root_moved");
+		let _ = tokio::fs::remove_dir_all(dir).await;
+		let _ = tokio::fs::remove_dir_all(moved).await;
+		tokio::fs::create_dir(dir).await.expect("mkdir failed");
+		tokio::fs::symlink("/etc/hostname", format!("{dir}/escape"))
+			.await
+			.expect("symlink failed");
+
+		let file_map = FileMap::with_root(dir).expect("root failed");
+		drop(file_map.try_writer("a", false).await.expect("writer failed"));
+		tokio::fs::rename(dir, moved).await.expect("rename failed");
+		file_map.get("a").await.expect("reader failed");
+		for path in ["../ammf_root_moved/a", "/etc/hostname", "escape"] {
+			let err = file_map.get(path).await.expect_err("escape should fail");
+			assert_eq!(err.kind(), ErrorKind::PermissionDenied, "{path}");
+		}
+		assert!(file_map.try_writer("../ammf_escape", false).await.is_err());
+		tokio::fs::remove_dir_all(moved).await.expect("cleanup failed");
+	}
+
+	#[tokio::test]
+	async fn test_file_policy() {
+		use std::os::unix::fs::MetadataExt;
+
+		let path = "/tmp/ammf_policy";
+		let _ = remove_file(path).await;
+		let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+		let file_map = FileMap::with_config(Config::new().file_mode(Some(0o640)).file_owner(Some(uid), Some(gid)));
+		drop(file_map.try_writer(path, false).await.expect("writer failed"));
+		let md = tokio::fs::metadata(path).await.expect("stat failed");
+		assert_eq!(md.mode() & 0o777, 0o640);
+		assert_eq!((md.uid(), md.gid()), (uid, gid));
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_read_your_writes() {
+		let path = "/tmp/ammf_ryw";
+		let file_map = FileMap::new();
+		let mut w = file_map.writer(path, false).await.expect("writer failed");
+		w.write_all(b"first").await.expect("write failed");
+		w.commit().await.expect("commit failed");
+
+		// a cached but unused mapping is replaced once the next writer commits
+		let f = file_map.get(path).await.expect("reader failed");
+		assert_eq!(f.read_slice_at(0, 5).expect("read failed"), b"first");
+		drop(f);
+
+		let mut w = file_map.writer(path, true).await.expect("writer failed");
+		assert!(file_map.get(path).await.is_err());
+		w.write_all(b" second").await.expect("write failed");
+		w.commit().await.expect("commit failed");
+
+		let mut buf = String::new();
+		let mut f = file_map.get(path).await.expect("reader failed");
+		f.read_to_string(&mut buf).await.expect("read failed");
+		assert_eq!(buf, "first second");
+
+		// outstanding readers keep writers out
+		assert!(file_map.try_writer(path, false).await.is_err());
+		drop(f);
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_op_context() {
+		let path = "/tmp/ammf_ctx";
+		let file_map = FileMap::new();
+		let w = file_map.writer(path, false).await.expect("writer failed");
+
+		let ctx = OpContext::new()
+			.with_timeout(Duration::from_millis(10))
+			.with_actor("req-1");
+		let err = file_map
+			.writer_ctx(path, false, &ctx)
+			.await
+			.err()
+			.expect("should time out");
+		assert_eq!(err.kind(), ErrorKind::TimedOut);
+		assert!(err.to_string().contains("req-1"));
+		assert_eq!(ctx.remaining(), Some(Duration::ZERO));
+		drop(w);
+
+		// the cancelled attempt didn't leave the path locked
+		let ctx = OpContext::new().with_timeout(Duration::from_secs(5));
+		drop(file_map.writer_ctx(path, false, &ctx).await.expect("writer failed"));
+		file_map.get_ctx(path, &ctx).await.expect("reader failed");
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_refresh_stale() {
+		let (path, gone) = ("/tmp/ammf_refresh", "/tmp/ammf_refresh_gone");
+		tokio::fs::write(path, b"old").await.expect("write failed");
+		tokio::fs::write(gone, b"gone").await.expect("write failed");
+		let file_map = FileMap::new();
+		let old = file_map.get(path).await.expect("get failed");
+		drop(file_map.get(gone).await.expect("get failed"));
+		assert_eq!(file_map.refresh_stale().await.expect("refresh failed"), 0);
+		assert!(!file_map.is_stale(path).await.expect("stat failed"));
+
+		// replaced by rename, the way most writers update files
+		tokio::fs::write("/tmp/ammf_refresh.tmp", b"newer")
+			.await
+			.expect("write failed");
+		tokio::fs::rename("/tmp/ammf_refresh.tmp", path)
+			.await
+			.expect("rename failed");
+		remove_file(gone).await.expect("delete failed");
+		assert!(file_map.is_stale(path).await.expect("stat failed"));
+		assert!(file_map.is_stale(gone).await.expect("stat failed"));
+		assert_eq!(file_map.refresh_stale().await.expect("refresh failed"), 2);
+		assert!(!file_map.is_stale(path).await.expect("stat failed"));
+		assert!(!file_map.reopen_if_changed(path).await.expect("refresh failed"));
+
+		let f = file_map.get(path).await.expect("get failed");
+		assert_eq!(f.read_slice_at(0, 5).expect("read failed"), b"newer");
+		assert_eq!(old.read_slice_at(0, 3).expect("read failed"), b"old");
+		assert!(file_map.files.lock().unwrap().get(gone).is_none());
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_for_each_until() {
+		let file_map = FileMap::new();
+		let paths = ["/tmp/ammf_until_a", "/tmp/ammf_until_b", "/tmp/ammf_until_c"];
+		for path in paths {
+			drop(file_map.try_writer(path, false).await.expect("writer failed"));
+			file_map.get(path).await.expect("reader failed");
+		}
+
+		let mut n = 0;
+		let flow = file_map
+			.for_each_until(false, async |_, _| {
+				n += 1;
+				Ok(if n == 2 {
+					ControlFlow::Break(())
+				} else {
+					ControlFlow::Continue(())
+				})
+			})
+			.await
+			.expect("for_each_until failed");
+		assert_eq!((flow, n), (ControlFlow::Break(()), 2));
+
+		let failed = file_map
+			.for_each_all(async |path, _| match path == paths[1] {
+				true => Err(Error::other("corrupt")),
+				false => Ok(()),
+			})
+			.await;
+		assert_eq!(failed.len(), 1);
+		assert_eq!(failed[0].0, paths[1]);
+
+		for path in paths {
+			remove_file(path).await.expect("delete failed");
+		}
+	}
+
+	#[tokio::test]
+	async fn test_writer_with() {
+		let path = "/tmp/ammf_writer_with";
+		tokio::fs::write(path, b"existing").await.expect("write failed");
+		let file_map = FileMap::new();
+		let must_be_new = OpenOptions::new().read(false).write(true).create_new(true);
+		let err = file_map
+			.writer_with(path, &must_be_new)
+			.await
+			.err()
+			.expect("file exists");
+		assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+		let err = file_map
+			.writer_with(path, &OpenOptions::new())
+			.await
+			.err()
+			.expect("read-only");
+		assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+		let truncate = OpenOptions::new().read(false).write(true).truncate(true).mode(0o600);
+		let mut w = file_map.writer_with(path, &truncate).await.expect("writer failed");
+		w.write_all(b"new").await.expect("write failed");
+		w.commit().await.expect("commit failed");
+		let f = file_map.get(path).await.expect("reader failed");
+		assert_eq!(f.read_slice_at(0, 3).expect("read failed"), b"new");
+		assert!(f.read_slice_at(0, 4).is_err());
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_get_bytes() {
+		let path = "/tmp/ammf_get_bytes";
+		tokio::fs::write(path, b"hot value").await.expect("write failed");
+		let file_map = FileMap::new();
+		let b = file_map.get_bytes(path, 16).await.expect("get_bytes failed");
+		assert_eq!(b, &b"hot value"[..]);
+		assert!(file_map.try_writer(path, false).await.is_err());
+		drop(b);
+		let err = file_map.get_bytes(path, 4).await.expect_err("too large");
+		assert_eq!(err.kind(), ErrorKind::FileTooLarge);
+
+		// a file that's too large on a miss is neither mapped nor cached
+		let file_map = FileMap::new();
+		let err = file_map.get_bytes(path, 4).await.expect_err("too large");
+		assert_eq!(err.kind(), ErrorKind::FileTooLarge);
+		assert!(file_map.files.lock().unwrap().is_empty());
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_exists() {
+		let path = "/tmp/ammf_exists";
+		let file_map = FileMap::new();
+		assert!(!file_map.exists(path).await.expect("stat failed"));
+		let w = file_map.writer(path, false).await.expect("writer failed");
+		assert!(file_map.exists(path).await.expect("stat failed"));
+		w.commit().await.expect("commit failed");
+		assert!(FileMap::new().exists(path).await.expect("stat failed"));
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_inflight_limits() {
+		let file_map = FileMap::with_config(Config::new().max_inflight_writers(Some(1)));
+		let w = file_map
+			.writer("/tmp/ammf_inflight_a", false)
+			.await
+			.expect("writer failed");
+		let err = file_map
+			.try_writer("/tmp/ammf_inflight_b", false)
+			.await
+			.err()
+			.expect("over the limit");
+		assert_eq!(err.to_string(), TOO_MANY_WRITERS);
+
+		// `writer` waits for the slot instead of failing, and gives up at the lock timeout
+		let (waited, ()) = tokio::join!(file_map.writer("/tmp/ammf_inflight_b", false), async {
+			yield_now().await;
+			drop(w);
+		});
+		drop(waited.expect("writer failed"));
+
+		// reloading the config keeps counting the held slot, shrinking below it retires it on release
+		let w = file_map
+			.writer("/tmp/ammf_inflight_a", false)
+			.await
+			.expect("writer failed");
+		file_map.update_config(Config::new().max_inflight_writers(Some(1)).lock_timeout(None));
+		assert!(file_map.try_writer("/tmp/ammf_inflight_b", false).await.is_err());
+		file_map.update_config(Config::new().max_inflight_writers(Some(0)));
+		drop(w);
+		assert!(file_map.try_writer("/tmp/ammf_inflight_b", false).await.is_err());
+		file_map.update_config(Config::new().max_inflight_writers(Some(1)));
+		let w = file_map
+			.try_writer("/tmp/ammf_inflight_b", false)
+			.await
+			.expect("writer failed");
+		assert!(file_map.try_writer("/tmp/ammf_inflight_a", false).await.is_err());
+		drop(w);
+		let timed = FileMap::with_config(
+			Config::new()
+				.max_inflight_writers(Some(1))
+				.lock_timeout(Some(Duration::from_millis(10))),
+		);
+		let w = timed
+			.writer("/tmp/ammf_inflight_a", false)
+			.await
+			.expect("writer failed");
+		let err = timed
+			.writer("/tmp/ammf_inflight_b", false)
+			.await
+			.err()
+			.expect("over the limit");
+		assert_eq!(err.kind(), ErrorKind::TimedOut);
+		drop(w);
+		drop(
+			file_map
+				.try_writer("/tmp/ammf_inflight_b", false)
+				.await
+				.expect("writer failed"),
+		);
+
+		file_map.update_config(Config::new().max_inflight_gets(Some(1)));
+		let (a, b) = tokio::join!(
+			file_map.get("/tmp/ammf_inflight_a"),
+			file_map.get("/tmp/ammf_inflight_b")
+		);
+		assert!(a.is_ok() && b.is_ok());
+		remove_file("/tmp/ammf_inflight_a").await.expect("delete failed");
+		remove_file("/tmp/ammf_inflight_b").await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_validator() {
+		use std::os::unix::fs::FileExt;
+
+		let (good, bad) = ("/tmp/ammf_valid_good", "/tmp/ammf_valid_bad");
+		tokio::fs::write(good, b"AMF1 data").await.expect("write failed");
+		tokio::fs::write(bad, b"#!/bin/sh").await.expect("write failed");
+		let file_map = FileMap::with_config(Config::new().validator(|f, _| {
+			let mut magic = [0; 4];
+			f.read_exact_at(&mut magic, 0)?;
+			match &magic {
+				b"AMF1" => Ok(()),
+				_ => Err(Error::new(ErrorKind::InvalidData, "bad magic")),
+			}
+		}));
+		file_map.get(good).await.expect("get failed");
+		let err = file_map.get(bad).await.expect_err("get should fail");
+		assert_eq!(err.kind(), ErrorKind::InvalidData);
+		remove_file(good).await.expect("delete failed");
+		remove_file(bad).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_get_single_flight() {
+		use std::sync::atomic::AtomicUsize;
+
+		let path = "/tmp/ammf_single_flight";
+		tokio::fs::write(path, b"hot").await.expect("write failed");
+		let opens = Arc::new(AtomicUsize::new(0));
+		let counter = opens.clone();
+		let (started_tx, mut started_rx) = tokio::sync::mpsc::unbounded_channel();
+		let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+		let release_rx = Mutex::new(release_rx);
+		// the first open blocks until every caller has claimed the path's slot
+		let file_map = FileMap::with_config(Config::new().validator(move |_, _| {
+			counter.fetch_add(1, Ordering::Relaxed);
+			let _ = started_tx.send(());
+			let _ = release_rx.lock().unwrap().recv();
+			Ok(())
+		}));
+		let gets = futures::future::try_join_all((0..50).map(|_| file_map.get(path)));
+		let release = async {
+			started_rx.recv().await.expect("validator ran");
+			// the map holds one reference to the slot, every waiting caller another
+			let claims = || file_map.loading.lock().unwrap().get(path).map(Arc::strong_count);
+			while claims() != Some(51) {
+				yield_now().await;
+			}
+			release_tx.send(()).expect("validator is waiting");
+		};
+		let (files, ()) = tokio::join!(gets, release);
+		let files = files.expect("get failed");
+		assert_eq!(opens.load(Ordering::Relaxed), 1);
+		assert!(files.iter().all(|f| f.as_bytes() == b"hot"));
+		assert!(file_map.loading.lock().unwrap().is_empty());
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_get_across_commit() {
+		use std::sync::{atomic::AtomicBool, mpsc::channel};
+
+		let path = "/tmp/ammf_get_across_commit";
+		tokio::fs::write(path, b"old").await.expect("write failed");
+		// the first map blocks inside the validator until the writer below has committed
+		let (entered_tx, mut entered) = tokio::sync::mpsc::unbounded_channel();
+		let (go, go_rx) = channel::<()>();
+		let go_rx = Mutex::new(go_rx);
+		let first = AtomicBool::new(true);
+		let file_map = FileMap::with_config(Config::new().validator(move |_, _| {
+			if first.swap(false, Ordering::AcqRel) {
+				entered_tx.send(()).expect("test is gone");
+				go_rx.lock().unwrap().recv().expect("test is gone");
+			}
+			Ok(())
+		}));
+
+		let write = async {
+			entered.recv().await.expect("map never started");
+			// the blocked map holds the old inode, so whatever it maps is stale once the writer commits
+			let tmp = format!("{path}.tmp");
+			tokio::fs::write(&tmp, b"new").await.expect("write failed");
+			tokio::fs::rename(&tmp, path).await.expect("rename failed");
+			let mut w = file_map.writer(path, true).await.expect("writer failed");
+			w.write_all(b"er").await.expect("write failed");
+			w.commit().await.expect("commit failed");
+			go.send(()).expect("map is gone");
+		};
+		let (f, ()) = tokio::join!(file_map.get(path), write);
+		assert_eq!(f.expect("get failed").as_bytes(), b"newer");
+		assert_eq!(file_map.get(path).await.expect("get failed").as_bytes(), b"newer");
+		remove_file(path).await.expect("delete failed");
+	}
+
+	#[tokio::test]
+	async fn test_get_mut() {
+		let path = "/tmp/ammf_get_mut";
+		tokio::fs::write(path, b"0123").await.expect("write failed");
+		let file_map = FileMap::new();
+
+		// a read-only mapping in use keeps the path from being mapped writable, and the other way round
+		let f = file_map.get(path).await.expect("get failed");
+		assert!(file_map.get_mut(path).await.is_err());
+		drop(f);
+		let mut m = file_map.get_mut(path).await.expect("get_mut failed");
+		assert!(file_map.get(path).await.is_err());
+		assert!(file_map.try_writer(path, true).await.is_err());
+		m.write_all(b"ab").await.expect("write failed");
+
+		let f = m.downgrade().await.expect("downgrade failed");
+		assert_eq!(f.as_bytes(), b"ab23");
+		assert!(file_map.get(path).await.expect("get failed").same_mapping(&f));
+		remove_file(path).await.expect("delete failed");
+	}
 }
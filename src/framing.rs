@@ -0,0 +1,248 @@
+//! Length-prefixed, checksummed record framing.
+//!
+//! Every frame is laid out as:
+//!
+//! | bytes | field                                          |
+//! |-------|------------------------------------------------|
+//! | 4     | magic, `b"AMF1"`                               |
+//! | 4     | payload length, little-endian `u32`            |
+//! | 4     | CRC32 of the length field and the payload, LE  |
+//! | len   | payload                                        |
+//!
+//! [`FrameReader`] verifies every frame and, when it hits garbage (a torn write, a flipped bit or
+//! a truncated tail), skips forward to the next magic instead of failing, so one bad record doesn't
+//! make the rest of the file unreadable.
+
+use crate::Result;
+use bytes::{Buf, Bytes, BytesMut};
+use std::io::{Error, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The magic bytes starting every frame.
+pub const MAGIC: [u8; 4] = *b"AMF1";
+
+/// The size of the frame header preceding the payload.
+pub const HEADER_LEN: usize = 12;
+
+/// The default upper bound on payload size accepted by [`FrameReader`].
+pub const DEFAULT_MAX_LEN: usize = 64 * 1024 * 1024;
+
+const FRAME_TOO_LARGE: &str = "frame payload is too large";
+
+fn checksum(len: [u8; 4], payload: &[u8]) -> u32 {
+	let mut h = crc32fast::Hasher::new();
+	h.update(&len);
+	h.update(payload);
+	h.finalize()
+}
+
+/// Encodes the header for `payload`.
+///
+/// # Errors
+///
+/// Returns an `ErrorKind::InvalidInput` error if the payload doesn't fit in a `u32` length.
+pub fn encode_header(payload: &[u8]) -> Result<[u8; HEADER_LEN]> {
+	let len = u32::try_from(payload.len())
+		.map_err(|_| Error::new(ErrorKind::InvalidInput, FRAME_TOO_LARGE))?
+		.to_le_bytes();
+	let mut hdr = [0; HEADER_LEN];
+	hdr[..4].copy_from_slice(&MAGIC);
+	hdr[4..8].copy_from_slice(&len);
+	hdr[8..].copy_from_slice(&checksum(len, payload).to_le_bytes());
+	Ok(hdr)
+}
+
+/// Writes frames to an `AsyncWrite`.
+#[derive(Debug)]
+pub struct FrameWriter<W> {
+	w: W,
+}
+
+impl<W: AsyncWrite + Unpin> FrameWriter<W> {
+	/// Creates a frame writer on top of `w`.
+	pub fn new(w: W) -> Self {
+		Self { w }
+	}
+
+	/// Writes `payload` as a single frame.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if the payload is larger than `u32::MAX` bytes or the
+	/// underlying writer fails.
+	pub async fn write_frame(&mut self, payload: &[u8]) -> Result<()> {
+		let hdr = encode_header(payload)?;
+		self.w.write_all(&hdr).await?;
+		self.w.write_all(payload).await
+	}
+
+	/// Flushes the underlying writer.
+	pub async fn flush(&mut self) -> Result<()> {
+		self.w.flush().await
+	}
+
+	/// Returns the underlying writer.
+	pub fn into_inner(self) -> W {
+		self.w
+	}
+}
+
+/// Reads and verifies frames from an `AsyncRead`, resynchronizing past corrupted data.
+#[derive(Debug)]
+pub struct FrameReader<R> {
+	r: R,
+	buf: BytesMut,
+	max_len: usize,
+	skipped: u64,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+	/// Creates a frame reader on top of `r`.
+	pub fn new(r: R) -> Self {
+		Self {
+			r,
+			buf: BytesMut::new(),
+			max_len: DEFAULT_MAX_LEN,
+			skipped: 0,
+		}
+	}
+
+	/// Sets the largest payload accepted, larger length fields are treated as corruption.
+	pub fn with_max_len(mut self, max_len: usize) -> Self {
+		self.max_len = max_len;
+		self
+	}
+
+	/// Returns the number of bytes skipped so far while resynchronizing.
+	pub fn skipped(&self) -> u64 {
+		self.skipped
+	}
+
+	/// Returns the next valid frame's payload, or `None` at the end of the input.
+	///
+	/// Invalid data is skipped and counted in [`FrameReader::skipped`]. A frame whose length runs past
+	/// the end of the input is treated the same way, so a corrupt length field only costs that frame
+	/// and a truncated final frame is skipped once no other magic follows it.
+	///
+	/// # Errors
+	///
+	/// This function only fails if the underlying reader does.
+	pub async fn next_frame(&mut self) -> Result<Option<Bytes>> {
+		loop {
+			if !self.fill(HEADER_LEN).await? {
+				return Ok(self.finish());
+			}
+			if self.buf[..4] != MAGIC {
+				self.resync();
+				continue;
+			}
+
+			let len_field: [u8; 4] = self.buf[4..8].try_into().unwrap();
+			let len = u32::from_le_bytes(len_field) as usize;
+			if len > self.max_len {
+				self.skip(1);
+				continue;
+			}
+			if !self.fill(HEADER_LEN + len).await? {
+				// the input ends before the frame does, either a truncated tail or a corrupt length
+				// field, so look for a frame in what's left
+				self.skip(1);
+				continue;
+			}
+
+			let crc = u32::from_le_bytes(self.buf[8..12].try_into().unwrap());
+			if crc != checksum(len_field, &self.buf[HEADER_LEN..HEADER_LEN + len]) {
+				self.skip(1);
+				continue;
+			}
+
+			let mut frame = self.buf.split_to(HEADER_LEN + len).freeze();
+			frame.advance(HEADER_LEN);
+			return Ok(Some(frame));
+		}
+	}
+
+	/// Returns the underlying reader, dropping any buffered data.
+	pub fn into_inner(self) -> R {
+		self.r
+	}
+
+	async fn fill(&mut self, n: usize) -> Result<bool> {
+		while self.buf.len() < n {
+			self.buf.reserve(n - self.buf.len());
+			if self.r.read_buf(&mut self.buf).await? == 0 {
+				return Ok(false);
+			}
+		}
+		Ok(true)
+	}
+
+	/// Drops bytes up to the next candidate magic, keeping a possible partial magic at the end.
+	fn resync(&mut self) {
+		let n = match memchr::memmem::find(&self.buf[1..], &MAGIC) {
+			Some(i) => i + 1,
+			None => self.buf.len() - (MAGIC.len() - 1),
+		};
+		self.skip(n);
+	}
+
+	fn skip(&mut self, n: usize) {
+		self.buf.advance(n);
+		self.skipped += n as u64;
+	}
+
+	fn finish(&mut self) -> Option<Bytes> {
+		let n = self.buf.len();
+		self.skip(n);
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_framing_resync() -> Result<()> {
+		let mut w = FrameWriter::new(vec![]);
+		w.write_frame(b"first").await?;
+		w.write_frame(b"second").await?;
+		w.write_frame(b"").await?;
+		w.write_frame(b"fourth").await?;
+		let mut data = w.into_inner();
+
+		// corrupt the second payload and append garbage plus a truncated frame
+		data[HEADER_LEN + 5 + HEADER_LEN] ^= 0xff;
+		data.extend_from_slice(b"junk");
+		data.extend_from_slice(&encode_header(b"tail")?);
+
+		let mut r = FrameReader::new(&data[..]);
+		let mut frames = vec![];
+		while let Some(f) = r.next_frame().await? {
+			frames.push(f);
+		}
+		assert_eq!(frames, [&b"first"[..], b"", b"fourth"]);
+		assert_eq!(r.skipped(), (HEADER_LEN + 6 + 4 + HEADER_LEN) as u64);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_framing_corrupt_len() -> Result<()> {
+		let mut w = FrameWriter::new(vec![]);
+		for payload in [&b"a"[..], b"bbbb", b"c", b"d"] {
+			w.write_frame(payload).await?;
+		}
+		let mut data = w.into_inner();
+		let at = HEADER_LEN + 1 + 4;
+		data[at..at + 4].copy_from_slice(&1000u32.to_le_bytes());
+
+		let mut r = FrameReader::new(&data[..]);
+		let mut frames = vec![];
+		while let Some(f) = r.next_frame().await? {
+			frames.push(f);
+		}
+		assert_eq!(frames, [&b"a"[..], b"c", b"d"]);
+		assert_eq!(r.skipped(), (HEADER_LEN + 4) as u64);
+		Ok(())
+	}
+}
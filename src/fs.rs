@@ -0,0 +1,173 @@
+use crate::{BlockingPool, Result};
+use std::{
+	fs::{self, File as StdFile},
+	io::{Error, ErrorKind},
+	mem,
+	os::fd::{AsFd, AsRawFd},
+	path::Path,
+};
+
+const NOT_UTF8: &str = "stream did not contain valid UTF-8";
+
+/// Reads the entire contents of a file into a byte vector, like `tokio::fs::read`.
+///
+/// The whole read runs as one `std::fs::read` call on the crate's blocking pool, which sizes the
+/// vector from the file's metadata upfront. Mapping the file first would only add a copy.
+///
+/// # Errors
+///
+/// This function will return an error if the file can't be opened or read.
+pub async fn read(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+	let path = path.as_ref().to_owned();
+	BlockingPool::global().spawn(move || fs::read(path)).await
+}
+
+/// Reads the entire contents of a file into a string, like `tokio::fs::read_to_string`.
+///
+/// # Errors
+///
+/// This function will return an `ErrorKind::InvalidData` error if the contents aren't valid UTF-8,
+/// or any error [`read`] returns.
+pub async fn read_to_string(path: impl AsRef<Path>) -> Result<String> {
+	String::from_utf8(read(path).await?).map_err(|_| Error::new(ErrorKind::InvalidData, NOT_UTF8))
+}
+
+/// Writes `data` to a file, creating or truncating it, like `tokio::fs::write`.
+///
+/// # Errors
+///
+/// This function will return an error if the file can't be created or written.
+pub async fn write(path: impl AsRef<Path>, data: impl AsRef<[u8]>) -> Result<()> {
+	let (path, data) = (path.as_ref().to_owned(), data.as_ref().to_owned());
+	BlockingPool::global().spawn(move || fs::write(path, data)).await
+}
+
+/// Copies the contents and permissions of `src` to `dst`, like `tokio::fs::copy`.
+///
+/// The copy happens in the kernel (`copy_file_range`, or `sendfile` as a fallback) on the blocking
+/// pool, without moving the data through userspace buffers.
+///
+/// # Returns
+///
+/// The number of bytes copied.
+///
+/// # Errors
+///
+/// This function will return an error if either file can't be opened or the copy fails.
+pub async fn copy(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<u64> {
+	let (src, dst) = (src.as_ref().to_owned(), dst.as_ref().to_owned());
+	BlockingPool::global().spawn(move || fs::copy(src, dst)).await
+}
+
+/// The file system a file lives on, see [`fs_info`] and [`crate::FileMap::fs_info`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FsInfo {
+	/// The file system's magic number, `statfs.f_type`.
+	pub magic: i64,
+	/// The file system's name, if it's one the crate knows.
+	pub name: Option<&'static str>,
+	/// The preferred I/O block size.
+	pub block_size: u64,
+	/// Whether the file system can share extents between files (`FICLONE`, `copy_file_range`).
+	pub reflink: bool,
+	/// Whether the file system can preallocate space with `fallocate(2)`.
+	pub fallocate: bool,
+}
+
+/// `(magic, name, reflink, fallocate)` of well-known file systems, from `linux/magic.h`.
+const KNOWN_FS: &[(i64, &str, bool, bool)] = &[
+	(0xEF53, "ext4", false, true),
+	(0x58465342, "xfs", true, true),
+	(0x9123683E, "btrfs", true, true),
+	(0xCA451A4E, "bcachefs", true, true),
+	(0xF2F52010, "f2fs", false, true),
+	(0x01021994, "tmpfs", false, true),
+	(0x6969, "nfs", false, true),
+	(0x4D44, "vfat", false, false),
+	(0x2011BAB0, "exfat", false, false),
+	(0x65735546, "fuse", false, false),
+	(0x794C7630, "overlayfs", false, false),
+];
+
+impl FsInfo {
+	/// Returns the file system `f` lives on, `f` may be an `O_PATH` descriptor.
+	pub(crate) fn of(f: &impl AsFd) -> Result<Self> {
+		let mut st: libc::statfs = unsafe { mem::zeroed() };
+		if unsafe { libc::fstatfs(f.as_fd().as_raw_fd(), &mut st) } != 0 {
+			return Err(Error::last_os_error());
+		}
+		let magic = st.f_type as i64;
+		let known = KNOWN_FS.iter().find(|fs| fs.0 == magic);
+		Ok(Self {
+			magic,
+			name: known.map(|fs| fs.1),
+			block_size: st.f_bsize as u64,
+			reflink: known.is_some_and(|fs| fs.2),
+			fallocate: known.is_some_and(|fs| fs.3),
+		})
+	}
+
+	/// Returns whether the file system is known not to support `fallocate(2)`.
+	pub(crate) fn lacks_fallocate(&self) -> bool {
+		self.name.is_some() && !self.fallocate
+	}
+}
+
+/// Returns the file system `path` lives on.
+///
+/// The flags are only set for file systems the crate knows to support them; on unknown ones they are
+/// `false` even though the operations may work.
+///
+/// # Errors
+///
+/// This function will return an error if `path` can't be opened.
+///
+/// # Example
+///
+/// ```ignore
+/// let info = async_mmap_file::fs_info("/var/lib/app").await?;
+/// if !info.reflink {
+///     warn!("snapshots will be full copies on {:?}", info.name);
+/// }
+/// ```
+pub async fn fs_info(path: impl AsRef<Path>) -> Result<FsInfo> {
+	let path = path.as_ref().to_owned();
+	BlockingPool::global()
+		.spawn(move || FsInfo::of(&StdFile::open(path)?))
+		.await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::fs::remove_file;
+
+	#[tokio::test]
+	async fn test_path_api() -> Result<()> {
+		let (src, dst) = ("/tmp/ammf_fs_src", "/tmp/ammf_fs_dst");
+		write(src, "hello").await?;
+		assert_eq!(copy(src, dst).await?, 5);
+		assert_eq!(read(dst).await?, b"hello");
+		assert_eq!(read_to_string(dst).await?, "hello");
+
+		write(src, b"").await?;
+		assert!(read(src).await?.is_empty());
+		write(src, [0xff]).await?;
+		assert_eq!(read_to_string(src).await.unwrap_err().kind(), ErrorKind::InvalidData);
+		remove_file(src).await?;
+		remove_file(dst).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_fs_info() -> Result<()> {
+		let info = fs_info("/tmp").await?;
+		assert!(info.block_size > 0);
+		assert!(info.name.is_some() || !(info.reflink || info.fallocate));
+		assert_eq!(
+			fs_info("/tmp/ammf_no_such_dir").await.unwrap_err().kind(),
+			ErrorKind::NotFound
+		);
+		Ok(())
+	}
+}
@@ -0,0 +1,193 @@
+use crate::{Config, MmapFile, Result};
+use std::{
+	path::{Path, PathBuf},
+	sync::{
+		Arc, Weak,
+		atomic::{AtomicBool, Ordering},
+	},
+	time::Duration,
+};
+use tokio::{
+	sync::{Mutex, watch},
+	time::Instant,
+};
+
+/// A lazily mapped file that unmaps itself after a period without access.
+///
+/// Useful for applications holding thousands of long-lived handles while only touching a few at a
+/// time: each access goes through [`IdleMmapFile::get`] or [`IdleMmapFile::with`], which remap on
+/// demand and push back the handle's idle deadline. A single timer task per handle drops the cached
+/// mapping once the deadline passes without another access.
+///
+/// Reads through an `MmapFile` returned by `get` don't count as accesses, and the memory is only
+/// released once the deadline passed *and* every such `MmapFile` has been dropped, so callers should
+/// use `with` or not hold on to them between accesses.
+///
+/// # Example
+///
+/// ```ignore
+/// let f = IdleMmapFile::new("/path/to/file", Duration::from_secs(30));
+/// let header = f.with(|m| m.read_u32_le_at(0)).await??;
+/// ```
+#[derive(Clone, Debug)]
+pub struct IdleMmapFile {
+	inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+	path: PathBuf,
+	config: Config,
+	idle: Duration,
+	state: Mutex<Option<MmapFile>>,
+	deadline: watch::Sender<Option<Instant>>,
+	timer: AtomicBool,
+}
+
+impl IdleMmapFile {
+	/// Creates a handle for `path`, nothing is opened until the first access.
+	///
+	/// # Arguments
+	///
+	/// * `path` - A path to the file to be mapped.
+	/// * `idle` - How long the mapping is kept after its last access.
+	pub fn new(path: impl AsRef<Path>, idle: Duration) -> Self {
+		Self::with_config(path, idle, Config::default())
+	}
+
+	/// Like [`IdleMmapFile::new`], mapping the file with the given configuration.
+	pub fn with_config(path: impl AsRef<Path>, idle: Duration, config: Config) -> Self {
+		Self {
+			inner: Arc::new(Inner {
+				path: path.as_ref().to_owned(),
+				config,
+				idle,
+				state: Mutex::new(None),
+				deadline: watch::Sender::new(None),
+				timer: AtomicBool::new(false),
+			}),
+		}
+	}
+
+	/// Returns the mapped file, mapping it first if it isn't currently mapped.
+	///
+	/// Every call pushes the idle deadline back.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if the file has to be remapped and that fails.
+	///
+	/// # Panics
+	///
+	/// This function will panic if called outside of a tokio runtime.
+	pub async fn get(&self) -> Result<MmapFile> {
+		let mut state = self.inner.state.lock().await;
+		let f = match state.as_ref() {
+			Some(f) => f.clone(),
+			None => {
+				let f = MmapFile::open_with_config(&self.inner.path, &self.inner.config).await?;
+				state.insert(f).clone()
+			}
+		};
+		self.touch();
+		Ok(f)
+	}
+
+	/// Runs `f` on the mapped file, mapping it first if it isn't currently mapped.
+	///
+	/// The idle deadline is pushed back both before and after `f` runs, so long accesses aren't cut
+	/// short by the timer.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if the file has to be remapped and that fails.
+	///
+	/// # Panics
+	///
+	/// This function will panic if called outside of a tokio runtime.
+	pub async fn with<T>(&self, f: impl FnOnce(&MmapFile) -> T) -> Result<T> {
+		let m = self.get().await?;
+		let res = f(&m);
+		self.touch();
+		Ok(res)
+	}
+
+	/// Returns whether the file is currently mapped by this handle. This doesn't count as an access.
+	pub async fn is_mapped(&self) -> bool {
+		self.inner.state.lock().await.is_some()
+	}
+
+	/// Drops the cached mapping right away instead of waiting for the idle timer.
+	pub async fn unmap(&self) {
+		let mut state = self.inner.state.lock().await;
+		state.take();
+		self.inner.deadline.send_replace(None);
+	}
+
+	/// Moves the idle deadline to `idle` from now, starting the handle's timer task on first use.
+	fn touch(&self) {
+		self.inner.deadline.send_replace(Some(Instant::now() + self.inner.idle));
+		if !self.inner.timer.swap(true, Ordering::AcqRel) {
+			tokio::spawn(expire(Arc::downgrade(&self.inner), self.inner.deadline.subscribe()));
+		}
+	}
+}
+
+/// Waits for the deadline and drops the mapping, for as long as the handle lives.
+async fn expire(inner: Weak<Inner>, mut deadline: watch::Receiver<Option<Instant>>) {
+	loop {
+		let Some(at) = *deadline.borrow_and_update() else {
+			if deadline.changed().await.is_err() {
+				return;
+			}
+			continue;
+		};
+		tokio::select! {
+			res = deadline.changed() => if res.is_err() {
+				return;
+			},
+			() = tokio::time::sleep_until(at) => {
+				let Some(inner) = inner.upgrade() else {
+					return;
+				};
+				let mut state = inner.state.lock().await;
+				// an access between the timer firing and taking the lock moved the deadline
+				if *inner.deadline.borrow() == Some(at) {
+					state.take();
+					inner.deadline.send_replace(None);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::fs::remove_file;
+
+	#[tokio::test(start_paused = true)]
+	async fn test_idle_unmap() -> Result<()> {
+		let path = "/tmp/ammf_idle";
+		tokio::fs::write(path, b"idle").await?;
+		let f = IdleMmapFile::new(path, Duration::from_secs(20));
+		assert!(!f.is_mapped().await);
+		assert_eq!(f.get().await?.read_u8_at(0)?, b'i');
+		assert!(f.is_mapped().await);
+
+		// accesses through `with` keep the mapping alive past the first deadline
+		for _ in 0..3 {
+			tokio::time::sleep(Duration::from_secs(15)).await;
+			assert_eq!(f.with(|m| m.read_u8_at(1)).await??, b'd');
+			assert!(f.is_mapped().await);
+		}
+
+		tokio::time::sleep(Duration::from_secs(21)).await;
+		assert!(!f.is_mapped().await);
+		assert_eq!(f.get().await?.read_u8_at(3)?, b'e');
+		f.unmap().await;
+		assert!(!f.is_mapped().await);
+		remove_file(path).await?;
+		Ok(())
+	}
+}
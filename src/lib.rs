@@ -1,8 +1,71 @@
-#![feature(file_lock)]
+mod blocking;
+pub use blocking::*;
+
+mod config;
+pub use config::*;
+
+mod context;
+pub use context::*;
+
+mod cursor;
+pub use cursor::*;
+
+mod digest;
+pub use digest::*;
+
 mod mmap_file;
 pub use mmap_file::*;
 
+mod mmap_file_mut;
+pub use mmap_file_mut::*;
+
+mod mmap_file_copy;
+pub use mmap_file_copy::*;
+
+mod options;
+pub use options::*;
+
 mod file_map;
 pub use file_map::*;
 
+mod fs;
+pub use fs::*;
+
+mod idle;
+pub use idle::*;
+
+pub mod framing;
+
+pub mod prelude;
+
+mod pipe;
+pub use pipe::*;
+
+mod pipeline;
+pub use pipeline::*;
+
+mod slice;
+pub use slice::*;
+
+mod spill;
+pub use spill::*;
+
+mod stream;
+pub use stream::*;
+
+mod syncer;
+pub use syncer::*;
+
+mod registry;
+pub use registry::*;
+
+mod window;
+pub use window::*;
+
+mod root;
+
+pub use bytes;
+#[cfg(feature = "unsafe-raw")]
+pub use memmap2;
+
 pub type Result<T> = std::io::Result<T>;
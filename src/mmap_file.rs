@@ -1,34 +1,154 @@
-use crate::Result;
+use crate::{
+	BlockingPool, Chunks, CombineDigest, Config, Digest, Follow, FsInfo, MmapFileCopy, MmapSlice, OpenOptions, Result,
+	SharedCursor, Split, config::with_timeout, registry,
+};
+use bytes::Bytes;
 use memmap2::Mmap;
 use std::{
-	fs::File as StdFile,
+	ffi::{CStr, CString},
+	fmt,
+	fs::{File as StdFile, FileType, Metadata},
 	io::{Error, ErrorKind, SeekFrom},
-	ops::Deref,
-	path::Path,
+	mem,
+	num::NonZeroUsize,
+	ops::{Bound, Range, RangeBounds},
+	os::{
+		fd::{AsFd, AsRawFd, FromRawFd, RawFd},
+		unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt},
+	},
+	path::{Path, PathBuf},
 	pin::Pin,
-	sync::{Arc, LazyLock},
+	ptr,
+	sync::{
+		Arc, LazyLock,
+		atomic::{AtomicU64, AtomicUsize, Ordering},
+	},
 	task::{Context, Poll},
+	time::Duration,
 };
 use tokio::{
 	fs::File as TokioFile,
-	io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf},
-	task::spawn_blocking,
+	io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncWrite, AsyncWriteExt, Interest, ReadBuf},
+	net::TcpStream,
 };
 
+const OUT_OF_BOUNDS: &str = "read out of bounds";
+const PAST_EOF: &str = "read starts past the end of the file";
+const TOO_LARGE: &str = "file exceeds the configured size limit";
+const IS_DIRECTORY: &str = "is a directory";
+const NOT_REGULAR: &str = "not a regular file";
+const PAST_WINDOW: &str = "write past the end of the window";
+
 static PAGE_SIZE: LazyLock<usize> = LazyLock::new(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE).min(4096) } as usize);
+const WARM_STEP: usize = 1 << 20;
+
+pub(crate) static OS_PAGE_SIZE: LazyLock<usize> =
+	LazyLock::new(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize);
+
+/// Access-pattern hints for [`MmapFile::advise`], see `madvise(2)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Advice {
+	/// No special treatment, the default.
+	Normal,
+	/// Pages will be read in order, so read ahead aggressively and drop them soon after.
+	Sequential,
+	/// Pages will be read in random order, so don't read ahead.
+	Random,
+	/// Pages will be needed soon, start reading them in.
+	WillNeed,
+	/// Pages won't be needed soon, the kernel may drop them; later reads fault them back in from the file.
+	DontNeed,
+}
+
+/// What the blocking half of opening a file hands back: the file, its mapping, identity, mtime and
+/// whether the mapping was populated.
+type Mapped = (StdFile, Mmap, FileId, (i64, i64), bool);
 
-/// A memory-mapped read-only file implementing AsyncRead / AsyncSeek
+/// A memory-mapped read-only file implementing AsyncRead / AsyncSeek, with AsyncWrite passing through to the file
 ///
 /// SAFETY:
 ///
 /// The file must be locked before reading from it.
 ///
-/// If the file is modified on disk, the universe may or may not implode.
+/// If the file is modified on disk, the universe may or may not implode, unless it was opened with
+/// [`Config::seal`], which maps a sealed private copy instead. [`Config::truncation_check`] turns
+/// most reads of a truncated file into errors instead of `SIGBUS`. Files cached by a [`crate::FileMap`]
+/// can be checked with [`crate::FileMap::is_stale`] and remapped with
+/// [`crate::FileMap::reopen_if_changed`].
+///
+/// Clones copy the cursor as it is at the time of the clone and move on independently from there;
+/// [`MmapFile::reader`] starts a fresh cursor instead, and [`MmapFile::shared_cursor`] returns a
+/// reader whose clones share one.
+///
+/// Files larger than the free address space, e.g. on 32-bit targets, can be read through a
+/// [`crate::MmapWindow`] instead.
 #[derive(Clone, Debug)]
 pub struct MmapFile {
 	f: Arc<TokioFile>,
 	m: Arc<Mmap>,
 	offset: usize,
+	id: FileId,
+	mtime: (i64, i64),
+	check_len: bool,
+	window: Option<u64>,
+	strict_eof: bool,
+	chunk: usize,
+	budget: Option<NonZeroUsize>,
+	since_yield: usize,
+	populated: bool,
+	pool: Arc<BlockingPool>,
+}
+
+/// The error wrapped by the `ErrorKind::UnexpectedEof` errors of reads that
+/// [`Config::truncation_check`] stopped, recoverable with `Error::get_ref` and `downcast_ref`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Truncated {
+	/// The end of the range that was about to be read.
+	pub end: u64,
+	/// The file's length at the time of the check.
+	pub len: u64,
+}
+
+impl fmt::Display for Truncated {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"file was truncated to {} bytes, reading up to {}",
+			self.len, self.end
+		)
+	}
+}
+
+impl std::error::Error for Truncated {}
+
+/// Identifies the file behind a mapping independently of the path used to open it.
+///
+/// Two handles with the same `FileId` map the same inode; a file replaced on disk (even under the same
+/// path) gets a different inode or generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileId {
+	/// The device containing the file.
+	pub dev: u64,
+	/// The inode number.
+	pub ino: u64,
+	/// The inode generation, or 0 if the filesystem doesn't report one.
+	pub generation: u64,
+}
+
+impl FileId {
+	pub(crate) fn of(f: &StdFile) -> Result<Self> {
+		let md = f.metadata()?;
+		let mut generation: libc::c_long = 0;
+		if unsafe { libc::ioctl(f.as_raw_fd(), libc::FS_IOC_GETVERSION, &mut generation) } != 0 {
+			generation = 0;
+		}
+		Ok(Self {
+			dev: md.dev(),
+			ino: md.ino(),
+			generation: generation as u64,
+		})
+	}
 }
 
 impl MmapFile {
@@ -42,23 +162,417 @@ impl MmapFile {
 	///
 	/// A `Result` containing the `MmapFile` instance if successful, or an error if not.
 	pub async fn open(p: impl AsRef<Path>) -> Result<Self> {
+		Self::open_with_config(p, &Config::default()).await
+	}
+
+	/// Opens a memory-mapped file asynchronously, honoring the mapping settings of `config`.
+	///
+	/// # Arguments
+	///
+	/// * `p` - A path to the file to be opened.
+	/// * `config` - The shared configuration, see [`Config`].
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::FileTooLarge` error if the file exceeds `Config::max_file_size`, or an
+	/// `ErrorKind::TimedOut` error if opening and mapping takes longer than `Config::io_timeout`.
+	///
+	/// Only regular files can be mapped: directories fail with `ErrorKind::IsADirectory`, and FIFOs,
+	/// sockets and devices with `ErrorKind::InvalidInput`, without blocking on the FIFO's writer.
+	pub async fn open_with_config(p: impl AsRef<Path>, config: &Config) -> Result<Self> {
 		let p = p.as_ref().to_owned();
-		let (f, m) = spawn_blocking(async move || -> Result<(StdFile, Mmap)> {
-			let f = StdFile::open(p)?;
-			let m = unsafe { memmap2::MmapOptions::new().populate().map_copy_read_only(&f)? };
-			Ok((f, m))
-		})
-		.await?
-		.await?;
+		let open = {
+			let p = p.clone();
+			move || StdFile::options().read(true).custom_flags(libc::O_NONBLOCK).open(p)
+		};
+		Self::map_with(&p, open, config).await
+	}
+
+	/// Maps a file that is already open, without opening its path a second time.
+	///
+	/// The file must have been opened for reading. Its position is ignored, the mapping covers the whole
+	/// file.
+	///
+	/// # Errors
+	///
+	/// Returns the same errors as [`MmapFile::open_with_config`], except those of opening the file.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = StdFile::open(dir.join(name))?;
+	/// check_owner(&f.metadata()?)?;
+	/// let f = MmapFile::from_std(f, &Config::default()).await?;
+	/// ```
+	pub async fn from_std(f: StdFile, config: &Config) -> Result<Self> {
+		let mut path = PathBuf::new();
+		if config.register {
+			path = std::fs::read_link(format!("/proc/self/fd/{}", f.as_raw_fd())).unwrap_or_default();
+		}
+		Self::map_with(&path, move || Ok(f), config).await
+	}
+
+	/// Maps an already open `tokio::fs::File`, see [`MmapFile::from_std`].
+	///
+	/// # Errors
+	///
+	/// Returns the same errors as [`MmapFile::from_std`].
+	pub async fn from_file(f: TokioFile, config: &Config) -> Result<Self> {
+		Self::from_std(f.into_std().await, config).await
+	}
+
+	/// Maps the open file `fd`, taking ownership of it, see [`MmapFile::from_std`].
+	///
+	/// # Errors
+	///
+	/// Returns the same errors as [`MmapFile::from_std`].
+	///
+	/// # Safety
+	///
+	/// `fd` must be an open file descriptor that nothing else owns or closes.
+	pub async unsafe fn from_raw_fd(fd: RawFd, config: &Config) -> Result<Self> {
+		Self::from_std(unsafe { StdFile::from_raw_fd(fd) }, config).await
+	}
+
+	/// Creates a zero-filled scratch mapping of `len` bytes that isn't backed by any file on disk.
+	///
+	/// A shorthand for [`MmapFile::memfd`] with a generic name: data written through `AsyncWrite` is
+	/// visible to reads from the mapping right away, and memory is only used for the pages written.
+	///
+	/// # Errors
+	///
+	/// Returns the `memfd_create(2)` error, if any, e.g. when out of memory.
+	pub async fn anonymous(len: u64) -> Result<Self> {
+		Self::memfd("anonymous", len).await
+	}
+
+	/// Creates a zero-filled mapping of `len` bytes backed by a memfd named `name`.
+	///
+	/// The memfd can be passed to other processes with [`MmapFile::as_file`], e.g. over a Unix socket,
+	/// to share memory with them. Writes through `AsyncWrite` land in it and are visible to every
+	/// mapping of it right away.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::InvalidInput` error if `name` contains a NUL byte, and the
+	/// `memfd_create(2)` error, if any.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut shm = MmapFile::memfd("frames", 16 << 20).await?;
+	/// shm.write_all(&frame).await?;
+	/// send_fd(&socket, shm.as_file().as_raw_fd())?;
+	/// ```
+	pub async fn memfd(name: &str, len: u64) -> Result<Self> {
+		let name = CString::new(name).map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+		let f = memfd(&name, 0)?;
+		f.set_len(len)?;
+		// populating would allocate every page up front
+		Self::from_std(f, &Config::new().populate(false)).await
+	}
+
+	/// Returns an [`OpenOptions`] builder, for opening files with custom flags or for writing.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::options().write(true).create(true).open("/path/to/file").await?;
+	/// ```
+	pub fn options() -> OpenOptions {
+		OpenOptions::new()
+	}
+
+	/// Opens a memory-mapped window of a file, mapping only `len` bytes starting at `offset`.
+	///
+	/// The window behaves like a file of `len` bytes: reads, seeks and the typed accessors are all
+	/// relative to `offset`. The offset doesn't need to be page-aligned, the mapping is aligned
+	/// internally. Writes through `AsyncWrite` land at the same position in the underlying file, but
+	/// are cut off at the end of the window.
+	///
+	/// # Arguments
+	///
+	/// * `p` - A path to the file to be opened.
+	/// * `offset` - The position in the file the window starts at.
+	/// * `len` - The length of the window.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the window extends past the end of the file, and
+	/// the errors of [`MmapFile::open_with_config`] otherwise. `Config::max_file_size` applies to `len`.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// // just the footer of a multi-GB file
+	/// let footer = MmapFile::open_range("/path/to/file", file_len - 4096, 4096, &Config::default()).await?;
+	/// ```
+	pub async fn open_range(p: impl AsRef<Path>, offset: u64, len: usize, config: &Config) -> Result<Self> {
+		let p = p.as_ref().to_owned();
+		let open = {
+			let p = p.clone();
+			move || StdFile::options().read(true).custom_flags(libc::O_NONBLOCK).open(p)
+		};
+		let end = offset
+			.checked_add(len as u64)
+			.ok_or_else(|| Error::new(ErrorKind::InvalidInput, OUT_OF_BOUNDS))?;
+		Self::map_range_with(&p, open, Some(offset..end), config).await
+	}
+
+	/// Maps the file returned by `open`, calling it on the blocking pool along with the mapping itself.
+	///
+	/// `open` should use `O_NONBLOCK` so opening a FIFO can't hang before it's rejected; the flag has no
+	/// effect on regular files. `path` is only used to describe the mapping in the registry.
+	pub(crate) async fn map_with(
+		path: &Path,
+		open: impl FnOnce() -> Result<StdFile> + Send + 'static,
+		config: &Config,
+	) -> Result<Self> {
+		Self::map_range_with(path, open, None, config).await
+	}
+
+	/// Like `map_with`, mapping only `window` of the file if set.
+	async fn map_range_with(
+		path: &Path,
+		open: impl FnOnce() -> Result<StdFile> + Send + 'static,
+		window: Option<Range<u64>>,
+		config: &Config,
+	) -> Result<Self> {
+		let (populate, max_size, seal) = (config.populate, config.max_file_size, config.seal);
+		let (populate_max, huge) = (config.populate_max_size, config.huge_pages);
+		let validator = config.validator.clone();
+		let base = window.as_ref().map(|w| w.start);
+		let map = config.blocking_pool.spawn(move || -> Result<Mapped> {
+			let f = open()?;
+			let meta = f.metadata()?;
+			check_regular(meta.file_type())?;
+			let len = window.as_ref().map_or(meta.len(), |w| w.end - w.start);
+			if window.as_ref().is_some_and(|w| w.end > meta.len()) {
+				return Err(Error::new(ErrorKind::UnexpectedEof, OUT_OF_BOUNDS));
+			}
+			if let Some(max) = max_size
+				&& len > max
+			{
+				return Err(Error::new(ErrorKind::FileTooLarge, TOO_LARGE));
+			}
+			if let Some(validate) = &validator {
+				(validate.0)(&f, &meta)?;
+			}
+			let id = FileId::of(&f)?;
+			let f = if seal { sealed_copy(&f)? } else { f };
+			let mut opts = memmap2::MmapOptions::new();
+			let populate = populate && populate_max.is_none_or(|max| len <= max);
+			if populate && !huge {
+				opts.populate();
+			}
+			if let Some(w) = &window {
+				opts.offset(w.start).len(len as usize);
+			}
+			let m = unsafe { opts.map_copy_read_only(&f)? };
+			if huge {
+				// only a hint, without THP support for the file system the mapping keeps regular pages
+				let _ = m.advise(memmap2::Advice::HugePage);
+				if populate {
+					self::populate(&m);
+				}
+			}
+			Ok((f, m, id, (meta.mtime(), meta.mtime_nsec()), populate))
+		});
+		let (f, m, id, mtime, populated) = with_timeout(config.io_timeout, "open", map).await?;
+		let m = Arc::new(m);
+		if config.register {
+			registry::register(path, id, &m, populated);
+		}
 
 		Ok(Self {
 			f: TokioFile::from_std(f).into(),
-			m: m.into(),
+			m,
 			offset: 0,
+			id,
+			mtime,
+			check_len: config.truncation_check && !config.seal,
+			window: base,
+			strict_eof: config.strict_eof,
+			chunk: config.read_chunk_size,
+			// a zero budget would never let a read through
+			budget: config.read_budget.and_then(NonZeroUsize::new),
+			since_yield: 0,
+			populated,
+			pool: config.blocking_pool.clone(),
 		})
 	}
 
-	/// Reads data into the provided buffer starting at the specified offset.
+	/// Advises the kernel how the whole mapping will be accessed.
+	///
+	/// # Errors
+	///
+	/// Returns the `madvise(2)` error, if any.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open_with_config("/path/to/big.log", &Config::new().populate(false)).await?;
+	/// f.advise(Advice::Sequential)?;
+	/// ```
+	pub fn advise(&self, advice: Advice) -> Result<()> {
+		self.advise_range(advice, 0..self.m.len() as u64)
+	}
+
+	/// Advises the kernel how `range` of the mapping will be accessed.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file, and
+	/// the `madvise(2)` error, if any.
+	pub fn advise_range(&self, advice: Advice, range: Range<u64>) -> Result<()> {
+		let len = range.end.saturating_sub(range.start) as usize;
+		self.read_slice_at(range.start, len)?;
+		let (m, start) = (&self.m, range.start as usize);
+		if len == 0 {
+			return Ok(());
+		}
+		match advice {
+			Advice::Normal => m.advise_range(memmap2::Advice::Normal, start, len),
+			Advice::Sequential => m.advise_range(memmap2::Advice::Sequential, start, len),
+			Advice::Random => m.advise_range(memmap2::Advice::Random, start, len),
+			Advice::WillNeed => m.advise_range(memmap2::Advice::WillNeed, start, len),
+			// SAFETY: the mapping is private and read-only, so there are no changes that could be lost
+			Advice::DontNeed => unsafe { m.unchecked_advise_range(memmap2::UncheckedAdvice::DontNeed, start, len) },
+		}
+	}
+
+	/// Starts reading `range` of the file into the page cache in the background, without waiting for it.
+	///
+	/// A shorthand for advising [`Advice::WillNeed`]. The kernel queues the reads and returns right away,
+	/// so later faults on the range are cheap instead of waiting for the disk on a runtime thread. To
+	/// also wait until the pages are mapped, use [`MmapFile::warm`], which faults them in on the
+	/// blocking pool, or [`Config::populate`], which does so for the whole file before `open` returns.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file.
+	pub fn prefetch(&self, range: Range<u64>) -> Result<()> {
+		self.advise_range(Advice::WillNeed, range)
+	}
+
+	/// Pins the mapping in memory with `mlock(2)`, so reads never page-fault.
+	///
+	/// The pages are faulted in on the blocking pool. The lock belongs to the mapping, so it's shared with
+	/// every clone of this handle and released by [`MmapFile::unlock`] or when the last of them is dropped.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::OutOfMemory` or `ErrorKind::PermissionDenied` error naming the current
+	/// `RLIMIT_MEMLOCK` if the mapping doesn't fit under it.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let index = file_map.get("/path/to/index").await?;
+	/// index.lock_in_memory().await?;
+	/// ```
+	pub async fn lock_in_memory(&self) -> Result<()> {
+		let m = self.m.clone();
+		self.pool
+			.spawn(move || {
+				m.lock().map_err(|err| match err.raw_os_error() {
+					Some(libc::ENOMEM | libc::EPERM) => {
+						let mut lim: libc::rlimit = unsafe { mem::zeroed() };
+						unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut lim) };
+						let msg = format!(
+							"mlock of {} bytes failed: {err} (RLIMIT_MEMLOCK is {} bytes)",
+							m.len(),
+							lim.rlim_cur
+						);
+						Error::new(err.kind(), msg)
+					}
+					_ => err,
+				})
+			})
+			.await
+	}
+
+	/// Releases a lock taken by [`MmapFile::lock_in_memory`] for every handle sharing the mapping.
+	///
+	/// # Errors
+	///
+	/// Returns the `munlock(2)` error, if any.
+	pub fn unlock(&self) -> Result<()> {
+		self.m.unlock()
+	}
+
+	/// Returns the underlying file.
+	///
+	/// Its own reads and seeks use the file's position, not this handle's cursor, and don't go through
+	/// the mapping.
+	pub fn as_file(&self) -> &TokioFile {
+		&self.f
+	}
+
+	/// Returns the underlying file mutably, or `None` while it's shared with clones of this handle.
+	pub fn file_mut(&mut self) -> Option<&mut TokioFile> {
+		Arc::get_mut(&mut self.f)
+	}
+
+	/// Queries the metadata of the underlying file, a passthrough to `tokio::fs::File::metadata`.
+	///
+	/// # Errors
+	///
+	/// Returns the `fstat(2)` error, if any.
+	pub async fn metadata(&self) -> Result<Metadata> {
+		self.f.metadata().await
+	}
+
+	/// Maps the file again privately, as a writable copy-on-write [`MmapFileCopy`] of this handle's range.
+	///
+	/// Nothing is copied up front, pages are only duplicated as they're written to. Changes never reach
+	/// the file, nor this handle or other mappings of it.
+	///
+	/// # Errors
+	///
+	/// Returns the `mmap(2)` error, if any.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let dataset = file_map.get("/data/table.bin").await?;
+	/// let mut scratch = dataset.map_copy()?;
+	/// scratch.write_at(row_offset, &patched_row)?;
+	/// ```
+	pub fn map_copy(&self) -> Result<MmapFileCopy> {
+		let mut opts = memmap2::MmapOptions::new();
+		opts.offset(self.window.unwrap_or(0)).len(self.m.len());
+		Ok(MmapFileCopy::new(unsafe { opts.map_copy(&*self.f)? }))
+	}
+
+	/// Returns whether the mapping was prefaulted at open time, see [`Config::populate_max_size`].
+	pub fn is_populated(&self) -> bool {
+		self.populated
+	}
+
+	/// Returns whether both handles share the same mapping, i.e. one is a clone of the other.
+	pub(crate) fn same_mapping(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.m, &other.m)
+	}
+
+	/// Returns whether `meta`, freshly read from this handle's path, describes a different file, or the
+	/// same file with a different size or modification time than when it was mapped.
+	pub(crate) fn is_stale(&self, meta: &Metadata) -> bool {
+		(meta.dev(), meta.ino()) != (self.id.dev, self.id.ino)
+			|| meta.len() != self.m.len() as u64
+			|| (meta.mtime(), meta.mtime_nsec()) != self.mtime
+	}
+
+	/// Opens a memory-mapped file, failing with `ErrorKind::TimedOut` if that takes longer than `timeout`.
+	///
+	/// A shorthand for [`MmapFile::open_with_config`] with only [`Config::io_timeout`] set.
+	pub async fn open_timeout(p: impl AsRef<Path>, timeout: Duration) -> Result<Self> {
+		Self::open_with_config(p, &Config::new().io_timeout(Some(timeout))).await
+	}
+
+	/// Reads data into the provided buffer starting at the specified offset, like `pread(2)`.
+	///
+	/// The cursor is neither used nor moved, so clones can share a handle's reads across tasks.
 	///
 	/// # Arguments
 	///
@@ -67,17 +581,31 @@ impl MmapFile {
 	///
 	/// # Returns
 	///
-	/// A `Result` containing the number of bytes read on success.
-	pub async fn read_at(&mut self, buf: &mut [u8], offset: u64) -> Result<usize> {
-		let cur_offset = self.offset;
-		self.offset = offset as usize;
-		let res = self.read(buf).await;
-		self.offset = cur_offset;
-		res
+	/// A `Result` containing the number of bytes read on success, 0 at or past the end of the file.
+	///
+	/// # Errors
+	///
+	/// With [`Config::strict_eof`] set, reads starting past the end of the file fail with
+	/// `ErrorKind::UnexpectedEof`.
+	pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+		let len = self.m.len();
+		let Some(start) = usize::try_from(offset).ok().filter(|&start| start <= len) else {
+			if self.strict_eof {
+				return Err(Error::new(ErrorKind::UnexpectedEof, PAST_EOF));
+			}
+			return Ok(0);
+		};
+		let n = buf.len().min(len - start);
+		self.check_truncated(start + n)?;
+		buf[..n].copy_from_slice(&self.m[start..start + n]);
+		Ok(n)
 	}
 
 	/// Writes the contents of the memory-mapped file to the given writer.
 	///
+	/// The data is written straight from the mapping, from the cursor to the end of the file, after
+	/// which the cursor is rewound.
+	///
 	/// # Arguments
 	///
 	/// * `writer` - An asynchronous writer implementing `AsyncWrite` and `Unpin`.
@@ -91,100 +619,1585 @@ impl MmapFile {
 	/// This function will return an error if reading from the memory-mapped file
 	/// or writing to the writer fails.
 	pub async fn write_to(&mut self, mut w: impl AsyncWrite + Unpin) -> Result<usize> {
-		let mut buf = vec![0; *PAGE_SIZE];
-		let mut total = 0;
-		loop {
-			let n = self.read(&mut buf).await?;
-			if n == 0 {
-				break;
+		let len = self.m.len();
+		let start = self.offset.min(len);
+		self.check_truncated(len)?;
+		w.write_all(&self.m[start..]).await?;
+		self.rewind();
+		Ok(len - start)
+	}
+
+	/// Copies the file from the cursor to the end into `dst`, in the kernel, without touching the mapping.
+	///
+	/// The data is copied with `copy_file_range(2)` on the blocking pool, which shares extents on file
+	/// systems with [reflink](crate::FsInfo::reflink) support. Copies between different file system
+	/// types, or where it isn't supported, use `sendfile(2)` instead. It's written at `dst`'s current position,
+	/// which is advanced; a `tokio::fs::File` must be flushed before it's handed in. The cursor is not
+	/// moved.
+	///
+	/// # Returns
+	///
+	/// The number of bytes copied, which is short only if the file was truncated meanwhile.
+	///
+	/// # Errors
+	///
+	/// This function returns the errors of the copy, e.g. `ErrorKind::StorageFull`.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/huge.bin").await?;
+	/// let dst = tokio::fs::File::create("/backup/huge.bin").await?;
+	/// f.copy_to_file(&dst).await?;
+	/// ```
+	pub async fn copy_to_file(&self, dst: &impl AsFd) -> Result<u64> {
+		let (src, dst) = (self.f.clone(), dst.as_fd().try_clone_to_owned()?);
+		let mut off = (self.window.unwrap_or(0) + self.offset.min(self.m.len()) as u64) as libc::off_t;
+		let len = self.m.len().saturating_sub(self.offset);
+		self.pool
+			.spawn(move || {
+				// copy_file_range only works within a file system (and type, for server-side copies)
+				let mut kernel_copy = FsInfo::of(&*src)?.magic == FsInfo::of(&dst)?.magic;
+				let (src, dst) = (src.as_raw_fd(), dst.as_raw_fd());
+				let mut copied = 0;
+				while copied < len {
+					let n = if kernel_copy {
+						unsafe { libc::copy_file_range(src, &mut off, dst, ptr::null_mut(), len - copied, 0) }
+					} else {
+						unsafe { libc::sendfile(dst, src, &mut off, len - copied) }
+					};
+					if n == 0 {
+						break;
+					}
+					if n > 0 {
+						copied += n as usize;
+						continue;
+					}
+					let err = Error::last_os_error();
+					match err.raw_os_error() {
+						Some(libc::EXDEV | libc::EINVAL | libc::ENOSYS | libc::EOPNOTSUPP) if kernel_copy => {
+							kernel_copy = false
+						}
+						Some(libc::EINTR) => {}
+						_ => return Err(err),
+					}
+				}
+				Ok(copied as u64)
+			})
+			.await
+	}
+
+	/// Sends `range` of the file to `stream` with `sendfile(2)`, without copying it through userspace.
+	///
+	/// The task waits for the socket to become writable whenever its buffer is full. If the kernel can't
+	/// `sendfile` from this file, the range is written from the mapping instead. The cursor is not moved.
+	///
+	/// # Returns
+	///
+	/// The number of bytes sent, which is short only if the file was truncated meanwhile.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file, and the
+	/// errors of writing to the socket.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = file_map.get(path).await?;
+	/// stream.write_all(&headers).await?;
+	/// f.send_to(&mut stream, ..).await?;
+	/// ```
+	pub async fn send_to(&self, stream: &mut TcpStream, range: impl RangeBounds<usize>) -> Result<u64> {
+		let r = self.resolve(range)?;
+		let (src, dst, base) = (self.f.as_raw_fd(), stream.as_raw_fd(), self.window.unwrap_or(0));
+		let mut pos = r.start;
+		while pos < r.end {
+			stream.writable().await?;
+			let sent = stream.try_io(Interest::WRITABLE, || {
+				let mut off = (base + pos as u64) as libc::off_t;
+				match unsafe { libc::sendfile(dst, src, &mut off, r.end - pos) } {
+					n if n < 0 => Err(Error::last_os_error()),
+					n => Ok(n as usize),
+				}
+			});
+			match sent {
+				Ok(0) => break,
+				Ok(n) => pos += n,
+				Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+				Err(err) if pos == r.start && matches!(err.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => {
+					self.check_truncated(r.end)?;
+					stream.write_all(&self.m[r.clone()]).await?;
+					return Ok(r.len() as u64);
+				}
+				Err(err) => return Err(err),
 			}
-			w.write_all(&buf[..n]).await?;
-			total = total + n;
 		}
-		self.seek(SeekFrom::Start(0)).await?;
-		Ok(total)
+		Ok((pos - r.start) as u64)
 	}
 
-	pub fn reader_count(&self) -> usize {
-		Arc::strong_count(&self.f)
+	/// Returns the whole mapping, for zero-copy parsing without `AsyncRead`.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/file").await?;
+	/// let header = parse_header(f.as_bytes())?;
+	/// ```
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.m
 	}
-}
 
-impl AsyncRead for MmapFile {
-	fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
-		let m = &self.m;
-		let len = buf.remaining().min(m.len() - self.offset).min(*PAGE_SIZE);
-		buf.put_slice(&m[self.offset..self.offset + len]);
-		self.offset += len;
-		Poll::Ready(Ok(()))
+	/// Returns the part of the mapping after the cursor, empty at the end of the file.
+	pub fn remaining(&self) -> &[u8] {
+		&self.m[self.offset.min(self.m.len())..]
 	}
-}
 
-impl AsyncSeek for MmapFile {
-	fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
-		let m = &self.m;
-		match position {
-			SeekFrom::Start(offset) => {
-				if offset > m.len() as u64 {
-					return Err(Error::new(ErrorKind::InvalidInput, "invalid position"));
-				}
-				self.offset = offset as usize;
-			}
-			SeekFrom::End(offset) => {
-				let pos = m.len() as i64 + offset;
-				if pos < 0 || pos > m.len() as i64 {
-					return Err(Error::new(ErrorKind::InvalidInput, "invalid position"));
-				}
-				self.offset = pos as usize;
-			}
-			SeekFrom::Current(offset) => {
-				let pos = self.offset as i64 + offset;
-				if pos < 0 || pos > m.len() as i64 {
-					return Err(Error::new(ErrorKind::InvalidInput, "invalid position"));
-				}
-				self.offset = pos as usize;
-			}
+	/// Returns the current position of the cursor.
+	pub fn position(&self) -> u64 {
+		self.offset as u64
+	}
+
+	/// Returns a new handle to the file with its own cursor at the start of the file.
+	///
+	/// Unlike `clone`, which copies the current position, the returned handle is explicitly independent
+	/// of this one, so handing it to another task never depends on how far this one has read.
+	pub fn reader(&self) -> Self {
+		Self {
+			offset: 0,
+			since_yield: 0,
+			..self.clone()
 		}
-		Ok(())
 	}
 
-	fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
-		let offset = self.offset as u64;
-		Poll::Ready(Ok(offset))
+	/// Returns a reader whose clones all advance one cursor, starting at this handle's position.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let cursor = f.shared_cursor();
+	/// for _ in 0..4 {
+	///     let mut c = cursor.clone();
+	///     tokio::spawn(async move { process_records(&mut c).await });
+	/// }
+	/// ```
+	pub fn shared_cursor(&self) -> SharedCursor {
+		SharedCursor::new(self.clone(), self.offset as u64)
 	}
-}
 
-impl Deref for MmapFile {
-	type Target = TokioFile;
+	/// Moves the cursor back to the start of the file.
+	///
+	/// Unlike `AsyncSeekExt::rewind` this is synchronous, since seeking a mapping never waits.
+	pub fn rewind(&mut self) {
+		self.offset = 0;
+	}
 
-	fn deref(&self) -> &Self::Target {
-		&self.f
+	/// Moves the cursor to `pos`.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::InvalidInput` error if `pos` is past the end of the file, like `seek` does.
+	pub fn set_position(&mut self, pos: u64) -> Result<()> {
+		self.offset = seek_pos(self.m.len(), self.offset, SeekFrom::Start(pos))?;
+		Ok(())
 	}
-}
 
-#[cfg(test)]
-mod tests {
-	use super::*;
-	use tokio::{
-		fs::{File, remove_file},
-		io::AsyncReadExt,
-	};
+	/// Returns the number of handles sharing this file, counting zero-copy `Bytes` of the mapping too.
+	pub fn reader_count(&self) -> usize {
+		Arc::strong_count(&self.f).max(Arc::strong_count(&self.m))
+	}
 
-	#[tokio::test]
-	async fn test_mmap() -> Result<()> {
-		const SIZE: usize = 10 * 1024 * 1024;
-		let path = "/tmp/x";
-		{
-			let mut f = File::create(&path).await.expect("create failed");
-			let buf = vec!['@' as u8; SIZE];
-			f.write_all(&buf).await.expect("write all failed");
-			f.flush().await.expect("flush failed");
+	/// Unwraps the underlying tokio file, unmapping it, if no other handle shares it.
+	///
+	/// Clones (including the one cached by a [`crate::FileMap`]) share the file, in which case the
+	/// handle is returned unchanged as the error. For a [`Config::seal`]ed file this is the memfd.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/file").await?;
+	/// let tokio_file = f.try_into_tokio().map_err(|_| Error::other("file is shared"))?;
+	/// ```
+	pub fn try_into_tokio(self) -> std::result::Result<TokioFile, Self> {
+		match Arc::try_unwrap(self.f) {
+			Ok(f) => Ok(f),
+			Err(f) => Err(Self { f, ..self }),
 		}
-		let mut f = MmapFile::open(&path).await.expect("open failed");
-		let mut buf = String::with_capacity(SIZE);
-		let n1 = f.read_to_string(&mut buf).await.expect("read to string failed");
-		assert_eq!(n1, SIZE);
-		remove_file(&path).await.expect("remove file failed");
+	}
+
+	/// Unwraps the underlying file as a `std::fs::File`, see [`MmapFile::try_into_tokio`].
+	pub async fn try_into_std(self) -> std::result::Result<StdFile, Self> {
+		Ok(self.try_into_tokio()?.into_std().await)
+	}
+
+	/// Returns the identity (device, inode, generation) of the mapped file, captured at open time.
+	pub fn identity(&self) -> FileId {
+		self.id
+	}
+
+	/// Returns whether both handles map the same file, regardless of the paths used to open them.
+	pub fn same_file(&self, other: &Self) -> bool {
+		self.id == other.id
+	}
+
+	/// Splits the mapping into records separated by `delim`, starting at the current position.
+	///
+	/// Records are zero-copy `Bytes` slices of the mapping, so the mapping stays alive as long as any
+	/// record does. The cursor is not moved.
+	///
+	/// # Arguments
+	///
+	/// * `delim` - The delimiter byte, e.g. `b'\n'` or `0`.
+	///
+	/// # Returns
+	///
+	/// A [`Split`] stream of `Bytes`.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/file").await?;
+	/// let mut lines = f.split(b'\n');
+	/// while let Some(line) = lines.next().await {
+	///     println!("{}", line.len());
+	/// }
+	/// ```
+	pub fn split(&self, delim: u8) -> Split {
+		Split::new(self.bytes().slice(self.offset.min(self.m.len())..), delim)
+	}
+
+	/// Splits the mapping into lines, starting at the current position, see [`MmapFile::split`].
+	///
+	/// Lines end at `\n`, and a `\r` right before it is dropped too. With `AsyncBufReadExt` in scope,
+	/// call this as `MmapFile::lines(&f)` to get the zero-copy stream instead of its copying `lines`.
+	pub fn lines(&self) -> Split {
+		Split::lines(self.bytes().slice(self.offset.min(self.m.len())..))
+	}
+
+	/// Returns the offset of the first occurrence of `needle` at or after the current position.
+	///
+	/// The search runs on the mapping with `memchr::memmem`, nothing is copied. The cursor is not moved.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/var/log/app.log").await?;
+	/// if let Some(at) = f.find(b"panicked at") {
+	///     let context = f.get(at as usize..)?;
+	/// }
+	/// ```
+	pub fn find(&self, needle: &[u8]) -> Option<u64> {
+		let start = self.offset.min(self.m.len());
+		memchr::memmem::find(&self.m[start..], needle).map(|i| (start + i) as u64)
+	}
+
+	/// Returns the offset of the last occurrence of `needle` between the cursor and the end of the
+	/// mapping. Like [`MmapFile::find`], the cursor is not moved.
+	pub fn rfind(&self, needle: &[u8]) -> Option<u64> {
+		let start = self.offset.min(self.m.len());
+		memchr::memmem::rfind(&self.m[start..], needle).map(|i| (start + i) as u64)
+	}
+
+	/// Streams the mapping in zero-copy chunks, starting at `offset`. The cursor is not moved.
+	///
+	/// # Arguments
+	///
+	/// * `offset` - The position of the first byte streamed, may be the length of the file.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if `offset` is past the end of the file.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/file").await?;
+	/// let mut chunks = f.stream_from(1 << 20)?;
+	/// while let Some(chunk) = chunks.next().await {
+	///     out.write_all(&chunk).await?;
+	/// }
+	/// ```
+	pub fn stream_from(&self, offset: u64) -> Result<Chunks> {
+		let start = usize::try_from(offset)
+			.ok()
+			.filter(|&start| start <= self.m.len())
+			.ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, OUT_OF_BOUNDS))?;
+		Ok(Chunks::new(self.bytes().slice(start..), *PAGE_SIZE, false))
+	}
+
+	/// Streams the whole mapping in zero-copy chunks from the end of the file toward the start.
+	///
+	/// Bytes within each chunk keep their order; only the chunks come in reverse. This makes finding the
+	/// last N lines of a large file a matter of scanning chunks with `memrchr` until enough newlines
+	/// were seen. The cursor is not moved.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/var/log/big.log").await?;
+	/// let mut chunks = f.read_backwards().chunk_size(64 << 10);
+	/// while let Some(chunk) = chunks.next().await {
+	///     // memchr::memrchr_iter(b'\n', &chunk) ...
+	/// }
+	/// ```
+	pub fn read_backwards(&self) -> Chunks {
+		Chunks::new(self.bytes(), *PAGE_SIZE, true)
+	}
+
+	/// Streams the file from the cursor on, waiting for and yielding data appended to it, like `tail -f`.
+	///
+	/// The stream never ends on its own. Windows opened with [`MmapFile::open_range`] never grow, so
+	/// following one stops yielding at the end of the window. Truncating the file while following it has
+	/// the same `SIGBUS` hazard as reading it does.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut f = MmapFile::open("/var/log/app.log").await?;
+	/// f.seek(SeekFrom::End(0)).await?;
+	/// let mut appended = f.follow().poll_interval(Duration::from_millis(100));
+	/// while let Some(chunk) = appended.next().await {
+	///     out.write_all(&chunk?).await?;
+	/// }
+	/// ```
+	pub fn follow(&self) -> Follow {
+		Follow::new(self.clone(), self.offset)
+	}
+
+	/// Copies `range` of the file into a new `Vec`.
+	///
+	/// If all of the range's pages are already resident the copy happens inline. Otherwise the range is
+	/// advised as `WillNeed` and copied on the blocking pool, so page faults never stall the runtime.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/file").await?;
+	/// let second_mb = f.copy_range_to_vec(1 << 20..2 << 20).await?;
+	/// ```
+	pub async fn copy_range_to_vec(&self, range: Range<u64>) -> Result<Vec<u8>> {
+		let len = range.end.saturating_sub(range.start) as usize;
+		let b = self.read_slice_at(range.start, len)?;
+		self.check_truncated(range.start as usize + len)?;
+		if b.is_empty() || is_resident(b) {
+			return Ok(b.to_vec());
+		}
+
+		let (start, m) = (range.start as usize, self.m.clone());
+		m.advise_range(memmap2::Advice::WillNeed, start, len)?;
+		self.pool.spawn(move || Ok(m[start..start + len].to_vec())).await
+	}
+
+	/// Faults in `range` of the mapping on the blocking pool, so later reads don't stall the runtime.
+	///
+	/// The range is split into steps that `concurrency` workers claim in turn, each populating its steps
+	/// with `MADV_POPULATE_READ`, or by touching every page on kernels that lack it. Meant for warming
+	/// mappings after a deploy without blocking request-serving tasks.
+	///
+	/// # Arguments
+	///
+	/// * `range` - The byte range to warm.
+	/// * `concurrency` - The number of blocking workers to use, at least one.
+	/// * `progress` - Called from the workers with the total number of bytes warmed so far.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/index").await?;
+	/// let len = f.metadata().await?.len();
+	/// f.warm(0..len, 4, move |done| gauge.set(done)).await?;
+	/// ```
+	pub async fn warm(
+		&self,
+		range: Range<u64>,
+		concurrency: usize,
+		progress: impl Fn(u64) + Send + Sync + 'static,
+	) -> Result<()> {
+		let len = range.end.saturating_sub(range.start) as usize;
+		self.read_slice_at(range.start, len)?;
+
+		let start = range.start as usize;
+		let state = Arc::new((AtomicUsize::new(0), AtomicU64::new(0), progress));
+		let workers = (0..concurrency.max(1)).map(|_| {
+			let (m, state) = (self.m.clone(), state.clone());
+			self.pool.spawn(move || {
+				let (next, done, progress) = &*state;
+				loop {
+					let i = next.fetch_add(WARM_STEP, Ordering::Relaxed);
+					if i >= len {
+						return Ok(());
+					}
+					let n = WARM_STEP.min(len - i);
+					populate(&m[start + i..start + i + n]);
+					progress(done.fetch_add(n as u64, Ordering::Relaxed) + n as u64);
+				}
+			})
+		});
+		futures::future::try_join_all(workers).await?;
+		Ok(())
+	}
+
+	/// Hashes the whole mapping with `H` on the blocking pool.
+	///
+	/// # Errors
+	///
+	/// Returns a [`Truncated`] error if [`Config::truncation_check`] is set and the file was truncated.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/blob").await?;
+	/// let crc = f.digest::<crc32fast::Hasher>().await?;
+	/// ```
+	pub async fn digest<H: Digest>(&self) -> Result<H::Output> {
+		self.check_truncated(self.m.len())?;
+		let m = self.m.clone();
+		self.pool
+			.spawn(move || {
+				let mut h = H::default();
+				h.update(&m);
+				Ok(h.finalize())
+			})
+			.await
+	}
+
+	/// Hashes the whole mapping with `H`, `chunk_size` bytes per blocking task, all running at once.
+	///
+	/// The result is the same as [`MmapFile::digest`]'s, but very large files are hashed on several
+	/// threads at once, as many as the blocking pool has.
+	///
+	/// # Errors
+	///
+	/// Returns a [`Truncated`] error if [`Config::truncation_check`] is set and the file was truncated.
+	///
+	/// # Panics
+	///
+	/// Panics if `chunk_size` is 0.
+	pub async fn digest_parallel<H: CombineDigest>(&self, chunk_size: usize) -> Result<H::Output> {
+		assert!(chunk_size > 0, "chunk size must be non-zero");
+		self.check_truncated(self.m.len())?;
+		let parts = (0..self.m.len()).step_by(chunk_size).map(|start| {
+			let m = self.m.clone();
+			self.pool.spawn(move || {
+				let mut h = H::default();
+				h.update(&m[start..m.len().min(start + chunk_size)]);
+				Ok(h)
+			})
+		});
+		let mut h = H::default();
+		for part in futures::future::try_join_all(parts).await? {
+			h.combine(&part);
+		}
+		Ok(h.finalize())
+	}
+
+	/// Fails with a [`Truncated`] error if the file no longer covers `..end`, when enabled.
+	fn check_truncated(&self, end: usize) -> Result<()> {
+		if !self.check_len {
+			return Ok(());
+		}
+		let mut st: libc::stat = unsafe { mem::zeroed() };
+		if unsafe { libc::fstat(self.f.as_raw_fd(), &mut st) } != 0 {
+			return Err(Error::last_os_error());
+		}
+		let (end, len) = (self.window.unwrap_or(0) + end as u64, st.st_size as u64);
+		if end > len {
+			return Err(Error::new(ErrorKind::UnexpectedEof, Truncated { end, len }));
+		}
+		Ok(())
+	}
+
+	/// Returns `range` of the mapping as zero-copy `Bytes`, which keep the mapping alive.
+	///
+	/// This bypasses `AsyncRead` entirely, e.g. to hand a file to hyper or tonic without copying it
+	/// into a `Vec`. The cursor is not moved.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/file").await?;
+	/// let body: Bytes = f.to_bytes(..)?;
+	/// let header = f.to_bytes(..16)?;
+	/// ```
+	pub fn to_bytes(&self, range: impl RangeBounds<usize>) -> Result<Bytes> {
+		let r = self.resolve(range)?;
+		self.check_truncated(r.end)?;
+		Ok(self.bytes().slice(r))
+	}
+
+	/// Returns `range` of the mapping, without moving the cursor.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/file").await?;
+	/// let magic = f.get(..4)?;
+	/// ```
+	pub fn get(&self, range: impl RangeBounds<usize>) -> Result<&[u8]> {
+		let r = self.resolve(range)?;
+		self.check_truncated(r.end)?;
+		Ok(&self.m[r])
+	}
+
+	/// Resolves `range` against the mapping, failing if it doesn't fit.
+	fn resolve(&self, range: impl RangeBounds<usize>) -> Result<Range<usize>> {
+		let start = match range.start_bound() {
+			Bound::Included(&n) => Some(n),
+			Bound::Excluded(&n) => n.checked_add(1),
+			Bound::Unbounded => Some(0),
+		};
+		let end = match range.end_bound() {
+			Bound::Included(&n) => n.checked_add(1),
+			Bound::Excluded(&n) => Some(n),
+			Bound::Unbounded => Some(self.m.len()),
+		};
+		match (start, end) {
+			(Some(start), Some(end)) if start <= end && end <= self.m.len() => Ok(start..end),
+			_ => Err(Error::new(ErrorKind::UnexpectedEof, OUT_OF_BOUNDS)),
+		}
+	}
+
+	/// Returns `range` of the file as an [`MmapSlice`], a reader with its own bounds and cursor.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = MmapFile::open("/path/to/file").await?;
+	/// for range in chunk_ranges {
+	///     let mut part = f.slice(range)?;
+	///     tokio::spawn(async move { process(&mut part).await });
+	/// }
+	/// ```
+	pub fn slice(&self, range: impl RangeBounds<usize>) -> Result<MmapSlice> {
+		self.to_bytes(range).map(MmapSlice::new)
+	}
+
+	/// Converts the handle into zero-copy `Bytes` of the whole mapping.
+	///
+	/// The file handle is released, only the mapping is kept alive by the returned `Bytes`.
+	pub fn into_bytes(self) -> Bytes {
+		self.bytes()
+	}
+
+	/// Returns the underlying mapping, for libraries that need to hold on to it themselves.
+	///
+	/// Prefer [`MmapFile::to_bytes`], which keeps the mapping alive the same way without exposing it.
+	///
+	/// # Safety
+	///
+	/// [`Config::truncation_check`] only guards the crate's own reads, not the returned mapping. Unless the
+	/// file was opened with [`Config::seal`], the caller must make sure it isn't truncated or modified for
+	/// as long as the mapping is used.
+	#[cfg(feature = "unsafe-raw")]
+	pub unsafe fn raw_mmap(&self) -> Arc<Mmap> {
+		self.m.clone()
+	}
+
+	/// Converts the handle into a stream of zero-copy chunks from the cursor to the end of the file.
+	///
+	/// Like [`MmapFile::into_bytes`], only the mapping is kept alive, by the chunks, so the stream can be
+	/// handed straight to an HTTP body or `StreamReader` without copying.
+	///
+	/// # Panics
+	///
+	/// Panics if `chunk_size` is 0.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let f = file_map.get(path).await?;
+	/// let body = f.into_stream(64 << 10).map(Ok::<_, Infallible>);
+	/// ```
+	pub fn into_stream(self, chunk_size: usize) -> Chunks {
+		let start = self.offset.min(self.m.len());
+		Chunks::new(self.bytes().slice(start..), chunk_size, false).chunk_size(chunk_size)
+	}
+
+	fn bytes(&self) -> Bytes {
+		Bytes::from_owner(MmapOwner(self.m.clone()))
+	}
+}
+
+macro_rules! read_int_at {
+	($($name:ident: $ty:ty, $from:ident, $what:literal;)*) => {
+		$(
+			#[doc = concat!("Reads ", $what, " `", stringify!($ty), "` at `offset` directly from the mapping.")]
+			///
+			/// # Errors
+			///
+			/// Returns an `ErrorKind::UnexpectedEof` error if the value doesn't fit in the file.
+			pub fn $name(&self, offset: u64) -> Result<$ty> {
+				let b = self.read_slice_at(offset, size_of::<$ty>())?;
+				Ok(<$ty>::$from(b.try_into().unwrap()))
+			}
+		)*
+	};
+}
+
+/// Typed, bounds-checked accessors that read straight from the mapping without moving the cursor.
+impl MmapFile {
+	/// Returns `len` bytes of the mapping starting at `offset`.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file.
+	pub fn read_slice_at(&self, offset: u64, len: usize) -> Result<&[u8]> {
+		let r = usize::try_from(offset)
+			.ok()
+			.and_then(|start| Some(start..start.checked_add(len)?))
+			.filter(|r| r.end <= self.m.len())
+			.ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, OUT_OF_BOUNDS))?;
+		self.check_truncated(r.end)?;
+		Ok(&self.m[r])
+	}
+
+	read_int_at! {
+		read_u8_at: u8, from_le_bytes, "a";
+		read_i8_at: i8, from_le_bytes, "a";
+		read_u16_le_at: u16, from_le_bytes, "a little-endian";
+		read_u16_be_at: u16, from_be_bytes, "a big-endian";
+		read_i16_le_at: i16, from_le_bytes, "a little-endian";
+		read_i16_be_at: i16, from_be_bytes, "a big-endian";
+		read_u32_le_at: u32, from_le_bytes, "a little-endian";
+		read_u32_be_at: u32, from_be_bytes, "a big-endian";
+		read_i32_le_at: i32, from_le_bytes, "a little-endian";
+		read_i32_be_at: i32, from_be_bytes, "a big-endian";
+		read_u64_le_at: u64, from_le_bytes, "a little-endian";
+		read_u64_be_at: u64, from_be_bytes, "a big-endian";
+		read_i64_le_at: i64, from_le_bytes, "a little-endian";
+		read_i64_be_at: i64, from_be_bytes, "a big-endian";
+		read_f32_le_at: f32, from_le_bytes, "a little-endian";
+		read_f32_be_at: f32, from_be_bytes, "a big-endian";
+		read_f64_le_at: f64, from_le_bytes, "a little-endian";
+		read_f64_be_at: f64, from_be_bytes, "a big-endian";
+	}
+}
+
+/// Returns whether every page backing `b` is in memory, so touching it won't fault.
+fn is_resident(b: &[u8]) -> bool {
+	let page = *OS_PAGE_SIZE;
+	let addr = b.as_ptr() as usize;
+	let start = addr & !(page - 1);
+	let len = addr + b.len() - start;
+	let mut pages = vec![0u8; len.div_ceil(page)];
+	let ok = unsafe { libc::mincore(start as *mut libc::c_void, len, pages.as_mut_ptr()) } == 0;
+	ok && pages.iter().all(|p| p & 1 == 1)
+}
+
+/// Rejects anything but regular files, which memmap2 would otherwise fail on in confusing ways.
+pub(crate) fn check_regular(ft: FileType) -> Result<()> {
+	let kind = if ft.is_file() {
+		return Ok(());
+	} else if ft.is_dir() {
+		return Err(Error::new(ErrorKind::IsADirectory, IS_DIRECTORY));
+	} else if ft.is_fifo() {
+		"fifo"
+	} else if ft.is_socket() {
+		"socket"
+	} else if ft.is_block_device() {
+		"block device"
+	} else if ft.is_char_device() {
+		"character device"
+	} else {
+		"unknown file type"
+	};
+	Err(Error::new(ErrorKind::InvalidInput, format!("{NOT_REGULAR}: {kind}")))
+}
+
+/// Faults in the pages backing `b`.
+fn populate(b: &[u8]) {
+	let page = *OS_PAGE_SIZE;
+	let addr = b.as_ptr() as usize;
+	let start = addr & !(page - 1);
+	let len = addr + b.len() - start;
+	if unsafe { libc::madvise(start as *mut libc::c_void, len, libc::MADV_POPULATE_READ) } == 0 {
+		return;
+	}
+	// pre-5.14 kernels, touch a byte of every page instead
+	for i in (0..b.len()).step_by(page) {
+		unsafe { std::ptr::read_volatile(b.as_ptr().add(i)) };
+	}
+}
+
+/// Copies `f` into a memfd and seals it against any further modification.
+///
+/// The copy uses positional reads from offset 0, so the file's position doesn't matter and isn't moved.
+fn sealed_copy(f: &StdFile) -> Result<StdFile> {
+	use std::{io::Write, os::unix::fs::FileExt};
+
+	let mut mfd = memfd(c"async_mmap_file", libc::MFD_ALLOW_SEALING)?;
+	let mut buf = vec![0; 64 << 10];
+	let mut offset = 0;
+	loop {
+		let n = match f.read_at(&mut buf, offset) {
+			Ok(0) => break,
+			Ok(n) => n,
+			Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+			Err(err) => return Err(err),
+		};
+		mfd.write_all(&buf[..n])?;
+		offset += n as u64;
+	}
+	let seals = libc::F_SEAL_WRITE | libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_SEAL;
+	if unsafe { libc::fcntl(mfd.as_raw_fd(), libc::F_ADD_SEALS, seals) } != 0 {
+		return Err(Error::last_os_error());
+	}
+	Ok(mfd)
+}
+
+/// Creates an anonymous in-memory file, `name` only shows up in `/proc/self/fd`.
+pub(crate) fn memfd(name: &CStr, flags: libc::c_uint) -> Result<StdFile> {
+	let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | flags) };
+	if fd < 0 {
+		return Err(Error::last_os_error());
+	}
+	Ok(unsafe { StdFile::from_raw_fd(fd) })
+}
+
+/// Keeps the mapping alive for `Bytes` handed out by [`MmapFile`].
+struct MmapOwner(Arc<Mmap>);
+
+impl AsRef<[u8]> for MmapOwner {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
+}
+
+impl AsyncRead for MmapFile {
+	fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		let this = &mut *self;
+		let m = &this.m;
+		if this.offset > m.len() {
+			if this.strict_eof {
+				return Poll::Ready(Err(Error::new(ErrorKind::UnexpectedEof, PAST_EOF)));
+			}
+			return Poll::Ready(Ok(()));
+		}
+		if this.budget.is_some_and(|budget| this.since_yield >= budget.get()) {
+			this.since_yield = 0;
+			cx.waker().wake_by_ref();
+			return Poll::Pending;
+		}
+		let len = buf.remaining().min(m.len() - this.offset).min(this.chunk);
+		this.check_truncated(this.offset + len)?;
+		buf.put_slice(&this.m[this.offset..this.offset + len]);
+		this.offset += len;
+		this.since_yield += len;
+		Poll::Ready(Ok(()))
+	}
+}
+
+/// `fill_buf` hands out the rest of the mapping directly, so `read_line`, `lines` and friends don't copy
+/// through an intermediate buffer. Likewise `tokio::io::copy_buf` writes straight from the mapping in
+/// as large slices as the writer takes, where `tokio::io::copy` copies everything through its own
+/// buffer first; [`MmapSlice`] and [`MmapFileCopy`] work the same way. With `AsyncBufReadExt` in scope, `f.split(b'\n')` and `f.lines()`
+/// resolve to its copying versions, call [`MmapFile::split`] as `MmapFile::split(&f, b'\n')` (and
+/// likewise [`MmapFile::lines`]) for the zero-copy ones.
+impl AsyncBufRead for MmapFile {
+	fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+		let this = self.get_mut();
+		let len = this.m.len();
+		if this.offset > len {
+			if this.strict_eof {
+				return Poll::Ready(Err(Error::new(ErrorKind::UnexpectedEof, PAST_EOF)));
+			}
+			return Poll::Ready(Ok(&[]));
+		}
+		this.check_truncated(len)?;
+		Poll::Ready(Ok(&this.m[this.offset..]))
+	}
+
+	fn consume(mut self: Pin<&mut Self>, amt: usize) {
+		self.offset = (self.offset + amt).min(self.m.len());
+	}
+}
+
+/// Writes go straight to the file with `pwrite` at the cursor, inline like reads.
+///
+/// The handle must have been opened for writing, otherwise writes fail with `EBADF`. Writes within the
+/// mapped range are visible to reads right away; writes that grow the file become readable after
+/// `flush`, which remaps this handle (other clones keep their mapping). Windows opened with
+/// [`MmapFile::open_range`] never grow, writes are cut off at the window's end and fail with
+/// `ErrorKind::WriteZero` once the cursor reaches it.
+///
+/// Unlike opening and the bulk operations, neither `pwrite` nor the `fstat` and `mmap` of the remap
+/// go through the blocking pool: they copy into and map the page cache without reading the file, so
+/// they only block under writeback pressure, and handing each write to another thread would cost more
+/// than it saves for the small patches this is meant for. Bulk writes belong in a
+/// [`crate::MmapFileMut`] or a [`crate::FileMap`] writer.
+impl AsyncWrite for MmapFile {
+	fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, mut buf: &[u8]) -> Poll<Result<usize>> {
+		if self.window.is_some() {
+			let room = self.m.len().saturating_sub(self.offset);
+			if room == 0 && !buf.is_empty() {
+				return Poll::Ready(Err(Error::new(ErrorKind::WriteZero, PAST_WINDOW)));
+			}
+			buf = &buf[..buf.len().min(room)];
+		}
+		let (fd, pos) = (self.f.as_raw_fd(), self.window.unwrap_or(0) + self.offset as u64);
+		let n = unsafe { libc::pwrite(fd, buf.as_ptr().cast(), buf.len(), pos as libc::off_t) };
+		if n < 0 {
+			return Poll::Ready(Err(Error::last_os_error()));
+		}
+		self.offset += n as usize;
+		Poll::Ready(Ok(n as usize))
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+		Poll::Ready(self.remap_if_grown())
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		self.poll_flush(cx)
+	}
+}
+
+impl MmapFile {
+	/// Remaps this handle if the file is now larger than its mapping, windows keep their size.
+	pub(crate) fn remap_if_grown(&mut self) -> Result<()> {
+		if self.window.is_some() {
+			return Ok(());
+		}
+		let mut st: libc::stat = unsafe { mem::zeroed() };
+		if unsafe { libc::fstat(self.f.as_raw_fd(), &mut st) } != 0 {
+			return Err(Error::last_os_error());
+		}
+		if st.st_size as usize > self.m.len() {
+			let m = unsafe { memmap2::MmapOptions::new().map_copy_read_only(&*self.f)? }.into();
+			registry::remapped(&self.m, &m);
+			self.m = m;
+			self.populated = false;
+			self.mtime = (st.st_mtime, st.st_mtime_nsec);
+		}
+		Ok(())
+	}
+}
+
+impl AsyncSeek for MmapFile {
+	fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+		self.offset = seek_pos(self.m.len(), self.offset, position)?;
+		Ok(())
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
+		let offset = self.offset as u64;
+		Poll::Ready(Ok(offset))
+	}
+}
+
+/// Resolves `position` against a mapping of `len` bytes and the cursor `cur`.
+pub(crate) fn seek_pos(len: usize, cur: usize, position: SeekFrom) -> Result<usize> {
+	let pos = match position {
+		SeekFrom::Start(offset) => i128::from(offset),
+		SeekFrom::End(offset) => len as i128 + i128::from(offset),
+		SeekFrom::Current(offset) => cur as i128 + i128::from(offset),
+	};
+	if pos < 0 || pos > len as i128 {
+		return Err(Error::new(ErrorKind::InvalidInput, "invalid position"));
+	}
+	Ok(pos as usize)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::StreamExt;
+	use tokio::{
+		fs::{File, remove_file},
+		io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+	};
+
+	#[tokio::test]
+	async fn test_mmap() -> Result<()> {
+		const SIZE: usize = 10 * 1024 * 1024;
+		let path = "/tmp/x";
+		{
+			let mut f = File::create(&path).await.expect("create failed");
+			let buf = vec![b'@'; SIZE];
+			f.write_all(&buf).await.expect("write all failed");
+			f.flush().await.expect("flush failed");
+		}
+		let mut f = MmapFile::open(&path).await.expect("open failed");
+		let mut buf = String::with_capacity(SIZE);
+		let n1 = f.read_to_string(&mut buf).await.expect("read to string failed");
+		assert_eq!(n1, SIZE);
+		remove_file(&path).await.expect("remove file failed");
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_split() -> Result<()> {
+		let path = "/tmp/ammf_split";
+		tokio::fs::write(path, b"a\n\nbc\nd").await?;
+		let f = MmapFile::open(path).await?;
+		let recs: Vec<Bytes> = f.split(b'\n').collect().await;
+		assert_eq!(recs, [&b"a"[..], b"", b"bc", b"d"]);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_chunks() -> Result<()> {
+		let path = "/tmp/ammf_chunks";
+		tokio::fs::write(path, b"0123456789").await?;
+		let f = MmapFile::open(path).await?;
+
+		let fwd: Vec<Bytes> = f.stream_from(2)?.chunk_size(3).collect().await;
+		assert_eq!(fwd, [&b"234"[..], b"567", b"89"]);
+		let rev: Vec<Bytes> = f.read_backwards().chunk_size(4).collect().await;
+		assert_eq!(rev, [&b"89"[..], b"4567", b"0123"]);
+		assert_eq!(f.stream_from(10)?.count().await, 0);
+		assert_eq!(
+			f.stream_from(11).err().map(|e| e.kind()),
+			Some(ErrorKind::UnexpectedEof)
+		);
+
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_truncation_check() -> Result<()> {
+		let path = "/tmp/ammf_truncated";
+		tokio::fs::write(path, b"0123456789").await?;
+		let mut f = MmapFile::open_with_config(path, &Config::new().truncation_check(true)).await?;
+		assert_eq!(f.copy_range_to_vec(0..10).await?, b"0123456789");
+
+		std::fs::OpenOptions::new().write(true).open(path)?.set_len(4)?;
+		let mut buf = [0; 10];
+		let err = f.read(&mut buf).await.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+		let t = err.get_ref().and_then(|e| e.downcast_ref::<Truncated>());
+		assert_eq!(t, Some(&Truncated { end: 10, len: 4 }));
+		assert!(f.copy_range_to_vec(2..8).await.is_err());
+		assert_eq!(f.copy_range_to_vec(0..4).await?, b"0123");
+		for err in [
+			f.get(..5).unwrap_err(),
+			f.read_slice_at(2, 3).unwrap_err(),
+			f.to_bytes(..).unwrap_err(),
+		] {
+			assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+		}
+		assert!(f.read_u32_le_at(4).is_err());
+		assert_eq!(f.read_u32_le_at(0)?, u32::from_le_bytes(*b"0123"));
+
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_registry() -> Result<()> {
+		let path = "/tmp/ammf_registry";
+		tokio::fs::write(path, b"0123").await?;
+		let find = || crate::live_mappings().into_iter().find(|m| m.path == Path::new(path));
+		drop(MmapFile::open(path).await?);
+		assert!(find().is_none());
+
+		let f = MmapFile::open_with_config(path, &Config::new().register(true)).await?;
+		let (_g, _lines) = (f.clone(), f.split(b'\n'));
+		let info = find().expect("mapping is registered");
+		assert_eq!((info.len, info.handles, info.id), (4, 3, f.identity()));
+		drop(f);
+		drop((_g, _lines));
+		assert!(find().is_none());
+
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_options() -> Result<()> {
+		let path = "/tmp/ammf_options";
+		tokio::fs::write(path, b"old contents").await?;
+		let opts = MmapFile::options().read(true).write(true).create(true).truncate(true);
+		let mut f = opts.open(path).await?;
+		assert_eq!(f.m.len(), 0);
+		f.write_all(b"new").await?;
+		f.flush().await?;
+		assert_eq!(f.read_slice_at(0, 3)?, b"new");
+
+		let err = MmapFile::options()
+			.create_new(true)
+			.write(true)
+			.open(path)
+			.await
+			.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+		let err = MmapFile::options().read(false).open(path).await.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidInput);
+		let err = opts
+			.clone()
+			.config(Config::new().seal(true))
+			.open(path)
+			.await
+			.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+		// files opened for writing get the config's file policy, an explicit mode wins
+		let config = Config::new().file_mode(Some(0o640));
+		let f = opts.clone().config(config.clone()).open_mut(path).await?;
+		assert_eq!(f.as_file().metadata().await?.mode() & 0o777, 0o640);
+		let f = opts.config(config).mode(0o604).open(path).await?;
+		assert_eq!(f.as_file().metadata().await?.mode() & 0o777, 0o604);
+
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_open_range() -> Result<()> {
+		let path = "/tmp/ammf_range";
+		let data: Vec<u8> = (0..3 * *OS_PAGE_SIZE).map(|i| (i % 251) as u8).collect();
+		tokio::fs::write(path, &data).await?;
+		let start = *OS_PAGE_SIZE + 7;
+		let mut f = MmapFile::open_range(path, start as u64, 100, &Config::default()).await?;
+		assert_eq!(f.m.len(), 100);
+		let mut buf = vec![];
+		f.read_to_end(&mut buf).await?;
+		assert_eq!(buf, &data[start..start + 100]);
+		assert_eq!(f.read_u8_at(0)?, data[start]);
+		assert!(f.read_u8_at(100).is_err());
+
+		let err = MmapFile::open_range(path, data.len() as u64 - 10, 11, &Config::default())
+			.await
+			.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+		// windows don't grow, writes at their end never reach the file
+		f.seek(SeekFrom::End(0)).await?;
+		assert_eq!(f.write(b"x").await.unwrap_err().kind(), ErrorKind::WriteZero);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_position() -> Result<()> {
+		let path = "/tmp/ammf_position";
+		tokio::fs::write(path, b"0123456789").await?;
+		let mut f = MmapFile::open(path).await?;
+		f.set_position(4)?;
+		assert_eq!(f.position(), 4);
+		assert_eq!(f.read_u8().await?, b'4');
+		assert_eq!(f.position(), 5);
+		assert!(f.set_position(11).is_err());
+		assert_eq!(f.remaining(), b"56789");
+		f.rewind();
+		assert_eq!((f.position(), f.read_u8().await?), (0, b'0'));
+		assert_eq!(f.as_bytes(), b"0123456789");
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_read_past_eof() -> Result<()> {
+		let path = "/tmp/ammf_past_eof";
+		tokio::fs::write(path, b"0123").await?;
+		let mut buf = [0; 4];
+		let f = MmapFile::open(path).await?;
+		assert_eq!(f.read_at(&mut buf, 4)?, 0);
+		assert_eq!(f.read_at(&mut buf, 100)?, 0);
+
+		let f = MmapFile::open_with_config(path, &Config::new().strict_eof(true)).await?;
+		assert_eq!(f.read_at(&mut buf, 4)?, 0);
+		let err = f.read_at(&mut buf, 100).unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+		assert_eq!(f.read_at(&mut buf, 2)?, 2);
+		assert_eq!((&buf[..2], f.position()), (&b"23"[..], 0));
+		assert_eq!(f.get(1..3)?, b"12");
+		assert!(f.get(3..=4).is_err());
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_to_bytes() -> Result<()> {
+		let path = "/tmp/ammf_to_bytes";
+		tokio::fs::write(path, b"0123456789").await?;
+		let f = MmapFile::open(path).await?;
+		assert_eq!(f.to_bytes(..)?, &b"0123456789"[..]);
+		assert_eq!(f.to_bytes(2..=4)?, &b"234"[..]);
+		assert_eq!(f.to_bytes(8..)?, &b"89"[..]);
+		assert!(f.to_bytes(..11).is_err());
+		assert!(f.to_bytes((Bound::Excluded(10), Bound::Unbounded)).is_err());
+		let b = f.into_bytes();
+		assert_eq!(b.len(), 10);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_slice() -> Result<()> {
+		let path = "/tmp/ammf_slice";
+		tokio::fs::write(path, b"0123456789").await?;
+		let f = MmapFile::open(path).await?;
+		let (mut a, mut b) = (f.slice(..4)?, f.slice(6..)?);
+		assert_eq!(f.reader_count(), 3);
+
+		let mut buf = String::new();
+		a.read_to_string(&mut buf).await?;
+		assert_eq!(buf, "0123");
+		b.seek(SeekFrom::Start(2)).await?;
+		assert_eq!((b.read_u8().await?, b.position()), (b'8', 3));
+		assert!(b.seek(SeekFrom::Start(5)).await.is_err());
+		assert!(f.slice(..11).is_err());
+
+		let mut out = Vec::new();
+		assert_eq!(tokio::io::copy_buf(&mut f.slice(2..8)?, &mut out).await?, 6);
+		assert_eq!(out, b"234567");
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_adaptive_populate() -> Result<()> {
+		let path = "/tmp/ammf_adaptive";
+		tokio::fs::write(path, b"0123456789").await?;
+		assert!(MmapFile::open(path).await?.is_populated());
+		let small = Config::new().populate_max_size(Some(10));
+		assert!(MmapFile::open_with_config(path, &small).await?.is_populated());
+		let tiny = Config::new().populate_max_size(Some(9));
+		assert!(!MmapFile::open_with_config(path, &tiny).await?.is_populated());
+		let off = Config::new().populate(false);
+		assert!(!MmapFile::open_with_config(path, &off).await?.is_populated());
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_advise() -> Result<()> {
+		let path = "/tmp/ammf_advise";
+		tokio::fs::write(path, vec![1u8; 3 * *OS_PAGE_SIZE]).await?;
+		let f = MmapFile::open(path).await?;
+		for advice in [Advice::Sequential, Advice::Random, Advice::WillNeed, Advice::Normal] {
+			f.advise(advice)?;
+		}
+		f.advise_range(Advice::DontNeed, 0..*OS_PAGE_SIZE as u64)?;
+		assert_eq!(f.read_u8_at(0)?, 1);
+		f.prefetch(*OS_PAGE_SIZE as u64..3 * *OS_PAGE_SIZE as u64)?;
+		assert!(f.advise_range(Advice::Random, 0..3 * *OS_PAGE_SIZE as u64 + 1).is_err());
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_lock_in_memory() -> Result<()> {
+		let path = "/tmp/ammf_mlock";
+		tokio::fs::write(path, b"pinned").await?;
+		let f = MmapFile::open(path).await?;
+		match f.lock_in_memory().await {
+			Ok(()) => f.unlock()?,
+			Err(err) => assert!(err.to_string().contains("RLIMIT_MEMLOCK"), "{err}"),
+		}
+		assert_eq!(f.as_bytes(), b"pinned");
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_huge_pages() -> Result<()> {
+		let path = "/tmp/ammf_huge";
+		tokio::fs::write(path, vec![3u8; 3 << 20]).await?;
+		let f = MmapFile::options().huge_pages(true).open(path).await?;
+		assert!(f.is_populated());
+		assert_eq!(f.as_bytes().len(), 3 << 20);
+		assert_eq!(f.read_u8_at((3 << 20) - 1)?, 3);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_follow() -> Result<()> {
+		let path = "/tmp/ammf_follow";
+		tokio::fs::write(path, b"a\n").await?;
+		let mut follow = MmapFile::open(path)
+			.await?
+			.follow()
+			.poll_interval(Duration::from_millis(5));
+		assert_eq!(follow.next().await.unwrap()?, &b"a\n"[..]);
+
+		let mut w = tokio::fs::OpenOptions::new().append(true).open(path).await?;
+		w.write_all(b"b\n").await?;
+		let next = tokio::time::timeout(Duration::from_secs(5), follow.next()).await?;
+		assert_eq!(next.unwrap()?, &b"b\n"[..]);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_into_stream() -> Result<()> {
+		let path = "/tmp/ammf_into_stream";
+		tokio::fs::write(path, b"0123456789").await?;
+		let mut f = MmapFile::open(path).await?;
+		f.set_position(2)?;
+		let chunks: Vec<Bytes> = f.into_stream(3).collect().await;
+		assert_eq!(chunks, ["234", "567", "89"]);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_buf_read() -> Result<()> {
+		use tokio::io::AsyncBufReadExt;
+		let path = "/tmp/ammf_buf_read";
+		tokio::fs::write(path, b"one\ntwo\nthree").await?;
+		let mut f = MmapFile::open(path).await?;
+		let mut line = String::new();
+		f.read_line(&mut line).await?;
+		assert_eq!((line.as_str(), f.position()), ("one\n", 4));
+		let mut lines = f.lines();
+		assert_eq!(lines.next_line().await?.as_deref(), Some("two"));
+		assert_eq!(lines.next_line().await?.as_deref(), Some("three"));
+		assert_eq!(lines.next_line().await?, None);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_read_chunk_size() -> Result<()> {
+		let path = "/tmp/ammf_read_chunk";
+		tokio::fs::write(path, vec![1u8; 100_000]).await?;
+		let mut buf = vec![0; 100_000];
+		let mut f = MmapFile::open(path).await?;
+		assert_eq!(f.read(&mut buf).await?, 4096);
+
+		let config = Config::new().read_chunk_size(1 << 20).read_budget(Some(50_000));
+		let mut f = MmapFile::open_with_config(path, &config).await?;
+		assert_eq!(f.read(&mut buf[..30_000]).await?, 30_000);
+		assert_eq!(f.read(&mut buf[..30_000]).await?, 30_000);
+		// over budget, the next read yields once before copying
+		let mut read = std::pin::pin!(f.read(&mut buf));
+		assert!(futures::poll!(read.as_mut()).is_pending());
+		assert_eq!(read.await?, 40_000);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_write_to() -> Result<()> {
+		let (path, copy) = ("/tmp/ammf_write_to", "/tmp/ammf_write_to_copy");
+		let data: Vec<u8> = (0..3 << 20).map(|i| i as u8).collect();
+		tokio::fs::write(path, &data).await?;
+		let mut f = MmapFile::open(path).await?;
+		f.set_position(10)?;
+
+		let mut out = Vec::new();
+		assert_eq!(f.write_to(&mut out).await?, data.len() - 10);
+		assert!(out == data[10..] && f.position() == 0);
+
+		let dst = File::create(copy).await?;
+		assert_eq!(f.copy_to_file(&dst).await?, data.len() as u64);
+		f.set_position(data.len() as u64 - 5)?;
+		assert_eq!(f.copy_to_file(&dst).await?, 5);
+		assert_eq!(f.position(), data.len() as u64 - 5);
+		let copied = tokio::fs::read(copy).await?;
+		assert!(copied[..data.len()] == data && copied[data.len()..] == data[data.len() - 5..]);
+		remove_file(path).await?;
+		remove_file(copy).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_send_to() -> Result<()> {
+		let path = "/tmp/ammf_send_to";
+		let data: Vec<u8> = (0..4 << 20).map(|i| (i % 251) as u8).collect();
+		tokio::fs::write(path, &data).await?;
+		let f = MmapFile::open(path).await?;
+
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+		let recv = tokio::spawn(async move {
+			let mut buf = Vec::new();
+			TcpStream::connect(addr).await?.read_to_end(&mut buf).await?;
+			Ok::<_, Error>(buf)
+		});
+		let (mut stream, _) = listener.accept().await?;
+		assert_eq!(f.send_to(&mut stream, 7..).await?, data.len() as u64 - 7);
+		assert!(f.send_to(&mut stream, ..data.len() + 1).await.is_err());
+		drop(stream);
+		assert!(recv.await.unwrap()? == data[7..]);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_digest() -> Result<()> {
+		let path = "/tmp/ammf_digest";
+		let data: Vec<u8> = (0..1_000_003).map(|i| (i * 7) as u8).collect();
+		tokio::fs::write(path, &data).await?;
+		let f = MmapFile::open(path).await?;
+		let want = crc32fast::hash(&data);
+		assert_eq!(f.digest::<crc32fast::Hasher>().await?, want);
+		assert_eq!(f.digest_parallel::<crc32fast::Hasher>(64 << 10).await?, want);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_find_lines() -> Result<()> {
+		let path = "/tmp/ammf_find_lines";
+		tokio::fs::write(path, b"a ok\r\nb err\nc ok").await?;
+		let mut f = MmapFile::open(path).await?;
+		assert_eq!(
+			(f.find(b"ok"), f.rfind(b"ok"), f.find(b"nope")),
+			(Some(2), Some(14), None)
+		);
+		let lines: Vec<Bytes> = f.lines().collect().await;
+		assert_eq!(lines, ["a ok", "b err", "c ok"]);
+		f.set_position(6)?;
+		assert_eq!((f.find(b"ok"), f.rfind(b"ok")), (Some(14), Some(14)));
+		f.set_position(15)?;
+		assert_eq!(f.rfind(b"ok"), None);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_from_std() -> Result<()> {
+		let path = "/tmp/ammf_from_std";
+		tokio::fs::write(path, b"already open").await?;
+		let config = Config::new().register(true);
+		let f = MmapFile::from_std(StdFile::open(path)?, &config).await?;
+		assert_eq!(f.as_bytes(), b"already open");
+		assert!(crate::live_mappings().iter().any(|m| m.path == Path::new(path)));
+		let f = MmapFile::from_file(File::open(path).await?, &Config::default()).await?;
+		assert_eq!(f.get(8..)?, b"open");
+		let fd = std::os::fd::IntoRawFd::into_raw_fd(StdFile::open(path)?);
+		let f = unsafe { MmapFile::from_raw_fd(fd, &config) }.await?;
+		assert_eq!(f.read_u8_at(0)?, b'a');
+
+		let err = MmapFile::from_std(StdFile::open("/tmp")?, &Config::default())
+			.await
+			.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::IsADirectory);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_memfd() -> Result<()> {
+		let mut shm = MmapFile::memfd("ammf_shm", 8).await?;
+		shm.write_all(b"hi").await?;
+		assert_eq!(shm.as_bytes(), b"hi\0\0\0\0\0\0");
+		let link = std::fs::read_link(format!("/proc/self/fd/{}", shm.as_file().as_raw_fd()))?;
+		assert!(link.to_string_lossy().contains("ammf_shm"));
+
+		let len = 4 * *OS_PAGE_SIZE;
+		let scratch = MmapFile::anonymous(len as u64).await?;
+		assert_eq!(
+			(scratch.as_bytes().len(), scratch.read_u8_at(len as u64 - 1)?),
+			(len, 0)
+		);
+		assert!(MmapFile::memfd("a\0b", 1).await.is_err());
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_warm() -> Result<()> {
+		let path = "/tmp/ammf_warm";
+		tokio::fs::write(path, vec![7u8; (WARM_STEP * 2) + 100]).await?;
+		let f = MmapFile::open_with_config(path, &Config::new().populate(false)).await?;
+
+		let seen = Arc::new(AtomicU64::new(0));
+		let max = seen.clone();
+		f.warm(10..f.m.len() as u64, 3, move |done| {
+			max.fetch_max(done, Ordering::Relaxed);
+		})
+		.await?;
+		assert_eq!(seen.load(Ordering::Relaxed), f.m.len() as u64 - 10);
+		assert!(f.warm(0..f.m.len() as u64 + 1, 1, |_| {}).await.is_err());
+
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_identity() -> Result<()> {
+		let (path, link) = ("/tmp/ammf_identity", "/tmp/ammf_identity_link");
+		tokio::fs::write(path, b"x").await?;
+		let _ = remove_file(link).await;
+		tokio::fs::hard_link(path, link).await?;
+		let a = MmapFile::open(path).await?;
+		let b = MmapFile::open(link).await?;
+		assert!(a.same_file(&b));
+
+		remove_file(path).await?;
+		tokio::fs::write(path, b"x").await?;
+		let c = MmapFile::open(path).await?;
+		assert!(!a.same_file(&c));
+		assert_ne!(a.identity(), c.identity());
+		remove_file(path).await?;
+		remove_file(link).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_sealed() -> Result<()> {
+		let path = "/tmp/ammf_sealed";
+		tokio::fs::write(path, b"sealed contents").await?;
+		let mut f = MmapFile::open_with_config(path, &Config::new().seal(true)).await?;
+		File::create(path).await?;
+
+		let mut buf = String::new();
+		f.read_to_string(&mut buf).await?;
+		assert_eq!(buf, "sealed contents");
+		assert!(f.as_file().set_len(0).await.is_err());
+
+		// the copy starts at offset 0 wherever the handed in file is positioned
+		tokio::fs::write(path, b"hello world").await?;
+		let mut std_f = std::fs::File::open(path)?;
+		std::io::Read::read_exact(&mut std_f, &mut [0; 6])?;
+		let f = MmapFile::from_std(std_f, &Config::new().seal(true)).await?;
+		assert_eq!(f.as_bytes(), b"hello world");
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_open_timeout() -> Result<()> {
+		let path = "/tmp/ammf_open_timeout";
+		tokio::fs::write(path, b"x").await?;
+		// a pool whose only slot is taken stalls the open
+		let pool = Arc::new(BlockingPool::new(1));
+		let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+		let busy = pool.spawn(move || {
+			let _ = release_rx.recv();
+			Ok(())
+		});
+		let config = Config::new()
+			.blocking_pool(pool.clone())
+			.io_timeout(Some(Duration::from_millis(20)));
+		// the slot is only released once the open has timed out
+		let open = async {
+			let res = MmapFile::open_with_config(path, &config).await;
+			release_tx.send(()).expect("busy job is waiting");
+			res
+		};
+		let (busy, err) = tokio::join!(busy, open);
+		busy?;
+		assert_eq!(err.unwrap_err().kind(), ErrorKind::TimedOut);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_configured_pool() -> Result<()> {
+		let path = "/tmp/ammf_configured_pool";
+		tokio::fs::write(path, b"pooled").await?;
+		let pool = Arc::new(BlockingPool::new(1));
+		let f = MmapFile::open_with_config(path, &Config::new().blocking_pool(pool.clone())).await?;
+		f.digest::<crc32fast::Hasher>().await?;
+		f.lock_in_memory().await?;
+		// opening, hashing and locking all ran on the configured pool
+		assert_eq!(pool.stats().completed, 3);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_not_regular() -> Result<()> {
+		let path = &format!("/tmp/ammf_fifo_not_regular_{}", std::process::id());
+		let _ = remove_file(path).await;
+		let c = CString::new(path.as_str()).unwrap();
+		assert_eq!(unsafe { libc::mkfifo(c.as_ptr(), 0o600) }, 0);
+		// rejected right away instead of blocking until a writer shows up
+		let err = MmapFile::open_timeout(path, Duration::from_secs(5)).await.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidInput);
+		remove_file(path).await?;
+
+		let err = MmapFile::open("/tmp").await.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::IsADirectory);
+		let err = MmapFile::open("/dev/null").await.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidInput);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_copy_range_to_vec() -> Result<()> {
+		let path = "/tmp/ammf_copy_range";
+		tokio::fs::write(path, b"0123456789").await?;
+		let cold = MmapFile::open_with_config(path, &Config::new().populate(false)).await?;
+		assert_eq!(cold.copy_range_to_vec(2..5).await?, b"234");
+		let hot = MmapFile::open(path).await?;
+		assert_eq!(hot.copy_range_to_vec(0..10).await?, b"0123456789");
+		assert!(hot.copy_range_to_vec(5..5).await?.is_empty());
+		assert_eq!(
+			hot.copy_range_to_vec(8..11).await.unwrap_err().kind(),
+			ErrorKind::UnexpectedEof
+		);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_into_file() -> Result<()> {
+		let path = "/tmp/ammf_into_file";
+		tokio::fs::write(path, b"into").await?;
+		let f = MmapFile::open(path).await?;
+		let clone = f.clone();
+		let f = f.try_into_tokio().expect_err("file is shared");
+		drop(clone);
+
+		let mut std_file = f.try_into_std().await.expect("file is unique");
+		let mut buf = String::new();
+		std::io::Read::read_to_string(&mut std_file, &mut buf)?;
+		assert_eq!(buf, "into");
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_write_passthrough() -> Result<()> {
+		let path = "/tmp/ammf_write";
+		tokio::fs::write(path, b"0123").await?;
+		let open = move || std::fs::OpenOptions::new().read(true).write(true).open(path);
+		let mut f = MmapFile::map_with(path.as_ref(), open, &Config::default()).await?;
+		f.write_all(b"ab").await?;
+		assert_eq!(f.read_slice_at(0, 4)?, b"ab23");
+
+		f.seek(SeekFrom::End(0)).await?;
+		f.write_all(b"tail").await?;
+		assert!(f.read_slice_at(4, 4).is_err());
+		f.flush().await?;
+		assert_eq!(f.read_slice_at(0, 8)?, b"ab23tail");
+
+		let mut ro = MmapFile::open(path).await?;
+		assert!(ro.write_all(b"x").await.is_err());
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_typed_reads() -> Result<()> {
+		let path = "/tmp/ammf_typed";
+		tokio::fs::write(path, [0x01, 0x02, 0x03, 0x04, 0x05]).await?;
+		let f = MmapFile::open(path).await?;
+		assert_eq!(f.read_u8_at(4)?, 5);
+		assert_eq!(f.read_u16_be_at(0)?, 0x0102);
+		assert_eq!(f.read_u32_le_at(1)?, 0x05040302);
+		assert_eq!(f.read_slice_at(3, 2)?, [4, 5]);
+		assert_eq!(f.read_u32_le_at(2).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+		assert!(f.read_slice_at(u64::MAX, 1).is_err());
+		remove_file(path).await?;
 		Ok(())
 	}
 }
@@ -0,0 +1,180 @@
+use crate::{Result, mmap_file::seek_pos};
+use memmap2::MmapMut;
+use std::{
+	io::{Error, ErrorKind, SeekFrom},
+	ops::Range,
+	pin::Pin,
+	task::{Context, Poll},
+};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+const OUT_OF_BOUNDS: &str = "write out of bounds";
+
+/// A private copy-on-write mapping of a file, see [`crate::MmapFile::map_copy`].
+///
+/// Writes only change this mapping's own copies of the pages they touch and never reach the file, so
+/// a read-only dataset can be edited in place for scratch work. The file can't grow: writes are cut
+/// off at its end. The written ranges are tracked and listed by [`MmapFileCopy::modified_ranges`].
+#[derive(Debug)]
+pub struct MmapFileCopy {
+	m: MmapMut,
+	offset: usize,
+	modified: Vec<Range<usize>>,
+}
+
+impl MmapFileCopy {
+	pub(crate) fn new(m: MmapMut) -> Self {
+		Self {
+			m,
+			offset: 0,
+			modified: Vec::new(),
+		}
+	}
+
+	/// Returns the length of the mapping.
+	pub fn len(&self) -> usize {
+		self.m.len()
+	}
+
+	/// Returns whether the mapping is empty.
+	pub fn is_empty(&self) -> bool {
+		self.m.is_empty()
+	}
+
+	/// Returns the mapping, including the changes written to it.
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.m
+	}
+
+	/// Copies `data` into the mapping at `offset`. The cursor is not moved.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::InvalidInput` error if the data doesn't fit before the end of the file.
+	pub fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+		let r = usize::try_from(offset)
+			.ok()
+			.and_then(|start| Some(start..start.checked_add(data.len())?))
+			.filter(|r| r.end <= self.m.len())
+			.ok_or_else(|| Error::new(ErrorKind::InvalidInput, OUT_OF_BOUNDS))?;
+		self.m[r.clone()].copy_from_slice(data);
+		self.mark(r);
+		Ok(())
+	}
+
+	/// Returns the ranges written to so far, sorted, with overlapping and adjacent ones merged.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// for r in scratch.modified_ranges() {
+	///     patch.push((r.start, scratch.as_bytes()[r.start as usize..r.end as usize].to_vec()));
+	/// }
+	/// ```
+	pub fn modified_ranges(&self) -> Vec<Range<u64>> {
+		self.modified.iter().map(|r| r.start as u64..r.end as u64).collect()
+	}
+
+	/// Records `r` as modified, merging it with the ranges it overlaps or touches.
+	fn mark(&mut self, mut r: Range<usize>) {
+		if r.is_empty() {
+			return;
+		}
+		let first = self.modified.partition_point(|m| m.end < r.start);
+		let last = self.modified.partition_point(|m| m.start <= r.end);
+		if first < last {
+			r.start = r.start.min(self.modified[first].start);
+			r.end = r.end.max(self.modified[last - 1].end);
+		}
+		self.modified.splice(first..last, [r]);
+	}
+}
+
+impl AsyncRead for MmapFileCopy {
+	fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		let start = self.offset.min(self.m.len());
+		let len = buf.remaining().min(self.m.len() - start);
+		buf.put_slice(&self.m[start..start + len]);
+		self.offset = start + len;
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncBufRead for MmapFileCopy {
+	fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+		let this = self.get_mut();
+		Poll::Ready(Ok(&this.m[this.offset.min(this.m.len())..]))
+	}
+
+	fn consume(mut self: Pin<&mut Self>, amt: usize) {
+		self.offset = (self.offset + amt).min(self.m.len());
+	}
+}
+
+/// Writes land in the private copy at the cursor; at the end of the file they write 0 bytes.
+impl AsyncWrite for MmapFileCopy {
+	fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+		let this = &mut *self;
+		let start = this.offset.min(this.m.len());
+		let n = buf.len().min(this.m.len() - start);
+		this.m[start..start + n].copy_from_slice(&buf[..n]);
+		this.mark(start..start + n);
+		this.offset = start + n;
+		Poll::Ready(Ok(n))
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncSeek for MmapFileCopy {
+	fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+		self.offset = seek_pos(self.m.len(), self.offset, position)?;
+		Ok(())
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
+		Poll::Ready(Ok(self.offset as u64))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::MmapFile;
+	use tokio::{
+		fs::remove_file,
+		io::{AsyncSeekExt, AsyncWriteExt},
+	};
+
+	#[tokio::test]
+	async fn test_map_copy() -> Result<()> {
+		let path = "/tmp/ammf_map_copy";
+		tokio::fs::write(path, b"0123456789").await?;
+		let f = MmapFile::open(path).await?;
+		let mut c = f.map_copy()?;
+		c.write_at(2, b"ab")?;
+		c.seek(SeekFrom::Start(8)).await?;
+		c.write_all(b"x").await?;
+		c.write_at(4, b"c")?;
+		assert_eq!(c.as_bytes(), b"01abc567x9");
+		assert_eq!(c.modified_ranges(), [2..5, 8..9]);
+		c.write_at(5, b"ddd")?;
+		let merged = c.modified_ranges();
+		assert_eq!((merged.len(), merged[0].clone()), (1, 2..9));
+
+		assert!(c.write_at(9, b"yz").is_err());
+		assert_eq!(c.write_all(b"yz").await.unwrap_err().kind(), ErrorKind::WriteZero);
+		assert_eq!(
+			(f.as_bytes(), tokio::fs::read(path).await?),
+			(&b"0123456789"[..], b"0123456789".to_vec())
+		);
+		remove_file(path).await?;
+		Ok(())
+	}
+}
@@ -0,0 +1,533 @@
+use crate::{
+	BlockingPool, Config, FsInfo, Result,
+	config::with_timeout,
+	mmap_file::{OS_PAGE_SIZE, check_regular, seek_pos},
+};
+use memmap2::MmapMut;
+use std::{
+	ffi::CString,
+	fmt,
+	fs::{File as StdFile, Metadata},
+	io::{Error, ErrorKind, SeekFrom},
+	ops::Range,
+	os::fd::{AsFd, AsRawFd, FromRawFd},
+	path::Path,
+	pin::Pin,
+	sync::Arc,
+	task::{Context, Poll, ready},
+};
+use tokio::{
+	fs::File as TokioFile,
+	io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf},
+};
+
+const OUT_OF_BOUNDS: &str = "range out of bounds";
+
+type Flush = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// Blocking work that has to finish before the next operation touches the file or its mapping.
+type Pending = Pin<Box<dyn Future<Output = Result<Resize>> + Send>>;
+
+/// How a finished [`Pending`] operation changed the file, if at all.
+#[derive(Default)]
+struct Resize {
+	len: Option<usize>,
+	m: Option<MmapMut>,
+}
+
+/// A memory-mapped file open for reading and writing, implementing AsyncRead / AsyncWrite / AsyncSeek.
+///
+/// Writes copy straight into the shared mapping and are visible to every other mapping of the file
+/// right away. `flush` persists them with `msync` on the blocking pool. Writes past the end grow the
+/// file with `ftruncate` on the blocking pool, remapping when they outgrow the mapping, whose capacity
+/// doubles each time so appending stays cheap. The file itself is always exactly as long as what was
+/// written, except that a write cancelled while growing the file may leave it grown, zero-filled.
+///
+/// Blocking work started by a call that was cancelled, e.g. a dropped `flush_range` future, is finished
+/// by the next call before it touches the mapping, so a cancelled call never makes later ones fail.
+///
+/// SAFETY:
+///
+/// As with [`crate::MmapFile`], the file must be locked against other writers, and truncating it under
+/// the mapping kills the process with `SIGBUS`.
+pub struct MmapFileMut {
+	f: TokioFile,
+	m: MmapMut,
+	len: usize,
+	offset: usize,
+	append: bool,
+	/// Where an append that had to wait for the file to grow goes once it's retried.
+	append_at: Option<usize>,
+	pool: Arc<BlockingPool>,
+	flushing: Option<Flush>,
+	pending: Option<Pending>,
+}
+
+impl MmapFileMut {
+	/// Opens and maps an existing file for reading and writing.
+	///
+	/// # Arguments
+	///
+	/// * `p` - A path to the file to be opened.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut f = MmapFileMut::open("/path/to/file").await?;
+	/// f.write_all(b"header").await?;
+	/// f.flush().await?;
+	/// ```
+	pub async fn open(p: impl AsRef<Path>) -> Result<Self> {
+		crate::OpenOptions::new().write(true).open_mut(p).await
+	}
+
+	/// Opens or creates the POSIX shared memory object `name` and maps it for reading and writing.
+	///
+	/// Every process mapping the same name shares the memory, writes are visible to all of them right
+	/// away. A new object is created with mode `0o600` and grown to `len` bytes, as is an existing one
+	/// that is smaller. The object lives until [`MmapFileMut::shm_unlink`] removes it, or the machine
+	/// reboots.
+	///
+	/// The object is mapped lazily, pages are only allocated as they're touched. Use
+	/// [`MmapFileMut::shm_open_with_config`] to populate it or use another blocking pool.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::InvalidInput` error if `name` contains a NUL byte, and the errors of
+	/// `shm_open(3)` and mapping the object.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// // in both processes
+	/// let mut shm = MmapFileMut::shm_open("/app-state", 1 << 20).await?;
+	/// ```
+	pub async fn shm_open(name: &str, len: u64) -> Result<Self> {
+		Self::shm_open_with_config(name, len, &Config::new().populate(false)).await
+	}
+
+	/// Like [`MmapFileMut::shm_open`], mapping the object with the given configuration.
+	///
+	/// The object gets the config's [`Config::file_mode`] and [`Config::file_owner`], its mode defaults
+	/// to `0o600`.
+	///
+	/// # Errors
+	///
+	/// Returns the same errors as [`MmapFileMut::shm_open`].
+	pub async fn shm_open_with_config(name: &str, len: u64, config: &Config) -> Result<Self> {
+		let name = CString::new(name).map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+		let policy = config.file_policy();
+		let open = move || {
+			let (flags, mode) = (
+				libc::O_RDWR | libc::O_CREAT | libc::O_CLOEXEC,
+				policy.create_mode_or(0o600),
+			);
+			let fd = unsafe { libc::shm_open(name.as_ptr(), flags, mode as libc::mode_t) };
+			if fd < 0 {
+				return Err(Error::last_os_error());
+			}
+			let f = unsafe { StdFile::from_raw_fd(fd) };
+			policy.apply(&f)?;
+			if f.metadata()?.len() < len {
+				f.set_len(len)?;
+			}
+			Ok(f)
+		};
+		Self::map_with(open, config).await
+	}
+
+	/// Removes the shared memory object `name`, mappings of it stay valid until they're dropped.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::InvalidInput` error if `name` contains a NUL byte, and the
+	/// `shm_unlink(3)` error, e.g. `ErrorKind::NotFound`.
+	pub fn shm_unlink(name: &str) -> Result<()> {
+		let name = CString::new(name).map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+		if unsafe { libc::shm_unlink(name.as_ptr()) } != 0 {
+			return Err(Error::last_os_error());
+		}
+		Ok(())
+	}
+
+	/// Maps the file returned by `open` for writing, calling it on the blocking pool.
+	pub(crate) async fn map_with(
+		open: impl FnOnce() -> Result<StdFile> + Send + 'static,
+		config: &Config,
+	) -> Result<Self> {
+		let populate = config.populate;
+		let map = config.blocking_pool.spawn(move || -> Result<(StdFile, MmapMut)> {
+			let f = open()?;
+			check_regular(f.metadata()?.file_type())?;
+			let mut opts = memmap2::MmapOptions::new();
+			if populate {
+				opts.populate();
+			}
+			let m = unsafe { opts.map_mut(&f)? };
+			Ok((f, m))
+		});
+		let (f, m) = with_timeout(config.io_timeout, "open", map).await?;
+
+		Ok(Self {
+			f: TokioFile::from_std(f),
+			len: m.len(),
+			m,
+			offset: 0,
+			append: false,
+			append_at: None,
+			pool: config.blocking_pool.clone(),
+			flushing: None,
+			pending: None,
+		})
+	}
+
+	/// Makes every write go to the end of the file, whatever the cursor's position.
+	pub(crate) fn set_append(&mut self, append: bool) {
+		self.append = append;
+	}
+
+	/// Returns the length of the file.
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Returns whether the file is empty.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Returns how long the file can grow before writes have to remap it.
+	pub fn capacity(&self) -> usize {
+		self.m.len()
+	}
+
+	/// Returns the underlying file.
+	///
+	/// Changing its length behind the mapping's back, e.g. with `set_len`, breaks the handle; only use
+	/// it for metadata and the like.
+	pub fn as_file(&self) -> &TokioFile {
+		&self.f
+	}
+
+	/// Queries the metadata of the underlying file, a passthrough to `tokio::fs::File::metadata`.
+	///
+	/// # Errors
+	///
+	/// Returns the `fstat(2)` error, if any.
+	pub async fn metadata(&self) -> Result<Metadata> {
+		self.f.metadata().await
+	}
+
+	/// Makes room for at least `additional` more bytes past the end of the file.
+	///
+	/// The disk space is allocated with `fallocate(FALLOC_FL_KEEP_SIZE)`, so later writes can't fail
+	/// with `ENOSPC` mid-way, and the mapping is extended up front, so they don't remap. The file's
+	/// length doesn't change.
+	///
+	/// # Errors
+	///
+	/// This function returns an error if the space can't be allocated (`ErrorKind::StorageFull`), or
+	/// the file system doesn't support `fallocate` (`ErrorKind::Unsupported`).
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut f = MmapFile::options().write(true).create(true).open_mut("/path/to/log").await?;
+	/// f.reserve(64 << 20).await?;
+	/// f.seek(SeekFrom::End(0)).await?;
+	/// f.write_all(&record).await?;
+	/// ```
+	pub async fn reserve(&mut self, additional: usize) -> Result<()> {
+		futures::future::poll_fn(|cx| self.poll_idle(cx)).await?;
+		let cap = self
+			.len
+			.checked_add(additional)
+			.ok_or_else(|| Error::from(ErrorKind::InvalidInput))?;
+		if FsInfo::of(&self.f)?.lacks_fallocate() {
+			return Err(Error::from(ErrorKind::Unsupported));
+		}
+		let f = self.dup()?;
+		let (off, n) = (self.len as libc::off_t, additional as libc::off_t);
+		let cap = (cap > self.m.len()).then_some(cap);
+		self.start(move || {
+			if unsafe { libc::fallocate(f.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, off, n) } != 0 {
+				return Err(Error::last_os_error());
+			}
+			Ok(Resize {
+				len: None,
+				m: cap.map(|cap| remap(&f, cap)).transpose()?,
+			})
+		});
+		futures::future::poll_fn(|cx| self.poll_pending(cx)).await
+	}
+
+	/// Starts growing the file to `end` bytes, remapping if it doesn't fit the mapping.
+	fn start_grow(&mut self, end: usize) -> Result<()> {
+		let f = self.dup()?;
+		let cap = (end > self.m.len()).then(|| end.max(self.m.len() * 2));
+		self.start(move || {
+			let m = cap.map(|cap| remap(&f, cap)).transpose()?;
+			f.set_len(end as u64)?;
+			Ok(Resize { len: Some(end), m })
+		});
+		Ok(())
+	}
+
+	/// Duplicates the file descriptor for use on the blocking pool.
+	fn dup(&self) -> Result<StdFile> {
+		Ok(self.f.as_fd().try_clone_to_owned()?.into())
+	}
+
+	/// Runs `f` on the blocking pool as the pending operation, which every later call waits for.
+	fn start(&mut self, f: impl FnOnce() -> Result<Resize> + Send + 'static) {
+		let pool = self.pool.clone();
+		self.pending = Some(Box::pin(async move { pool.spawn(f).await }));
+	}
+
+	/// Persists the writes to `range` of the file with `msync`, on the blocking pool.
+	///
+	/// Unlike `flush`, which syncs the whole mapping, this only waits for the given range, e.g. a
+	/// record just written to a shared mapping.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::UnexpectedEof` error if the range extends past the end of the file, and
+	/// the `msync(2)` error, if any.
+	pub async fn flush_range(&mut self, range: Range<usize>) -> Result<()> {
+		futures::future::poll_fn(|cx| self.poll_idle(cx)).await?;
+		if range.start > range.end || range.end > self.len {
+			return Err(Error::new(ErrorKind::UnexpectedEof, OUT_OF_BOUNDS));
+		}
+		let addr = self.m.as_ptr() as usize + range.start;
+		self.start(move || msync(addr, range.end - range.start).map(|_| Resize::default()));
+		futures::future::poll_fn(|cx| self.poll_pending(cx)).await
+	}
+
+	/// Drives the pending operation, if any, to completion and applies its result.
+	fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		let Some(pending) = &mut self.pending else {
+			return Poll::Ready(Ok(()));
+		};
+		let res = ready!(pending.as_mut().poll(cx));
+		self.pending = None;
+		let Resize { len, m } = res?;
+		if let Some(m) = m {
+			self.m = m;
+		}
+		if let Some(len) = len {
+			self.len = len;
+		}
+		Poll::Ready(Ok(()))
+	}
+
+	/// Drives a flush started by an earlier `poll_flush`, if any, to completion.
+	fn poll_pending_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		let Some(flush) = &mut self.flushing else {
+			return Poll::Ready(Ok(()));
+		};
+		let res = ready!(flush.as_mut().poll(cx));
+		self.flushing = None;
+		Poll::Ready(res)
+	}
+
+	/// Waits until no blocking work on the file or its mapping is in flight.
+	fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		ready!(self.poll_pending(cx))?;
+		self.poll_pending_flush(cx)
+	}
+}
+
+/// Maps `f` for writing with a capacity of at least `cap` bytes, which may extend past its end.
+fn remap(f: &StdFile, cap: usize) -> Result<MmapMut> {
+	let cap = cap.next_multiple_of(*OS_PAGE_SIZE);
+	unsafe { memmap2::MmapOptions::new().len(cap).map_mut(f) }
+}
+
+/// Persists `len` bytes of the mapping at `addr` with `msync(2)`.
+///
+/// Takes the address instead of the mapping, so a cancelled flush doesn't keep the mapping borrowed or
+/// alive. The handle finishes its in-flight flushes before remapping, and if it's dropped first the
+/// call fails with `ENOMEM` without touching the memory.
+fn msync(addr: usize, len: usize) -> Result<()> {
+	if len == 0 {
+		return Ok(());
+	}
+	let aligned = addr - addr % *OS_PAGE_SIZE;
+	match unsafe { libc::msync(aligned as *mut libc::c_void, len + addr - aligned, libc::MS_SYNC) } {
+		0 => Ok(()),
+		_ => Err(Error::last_os_error()),
+	}
+}
+
+impl fmt::Debug for MmapFileMut {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("MmapFileMut")
+			.field("f", &self.f)
+			.field("len", &self.m.len())
+			.field("offset", &self.offset)
+			.field("append", &self.append)
+			.field("flushing", &self.flushing.is_some())
+			.field("pending", &self.pending.is_some())
+			.finish()
+	}
+}
+
+impl AsyncRead for MmapFileMut {
+	fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		let m = &self.m;
+		let start = self.offset.min(self.len);
+		let len = buf.remaining().min(self.len - start);
+		buf.put_slice(&m[start..start + len]);
+		self.offset = start + len;
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncWrite for MmapFileMut {
+	fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+		let this = &mut *self;
+		if let Err(err) = ready!(this.poll_idle(cx)) {
+			this.append_at = None;
+			return Poll::Ready(Err(err));
+		}
+		let start = match this.append {
+			true => this.append_at.unwrap_or(this.len),
+			false => this.offset.min(this.len),
+		};
+		let end = start
+			.checked_add(buf.len())
+			.ok_or_else(|| Error::from(ErrorKind::InvalidInput))?;
+		if end > this.len {
+			// the retried call finds the file already grown by the first attempt
+			this.start_grow(end)?;
+			this.append_at = this.append.then_some(start);
+			if let Err(err) = ready!(this.poll_pending(cx)) {
+				this.append_at = None;
+				return Poll::Ready(Err(err));
+			}
+		}
+		this.m[start..end].copy_from_slice(buf);
+		this.offset = end;
+		this.append_at = None;
+		Poll::Ready(Ok(buf.len()))
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		let this = &mut *self;
+		ready!(this.poll_pending(cx))?;
+		if this.flushing.is_none() {
+			let (addr, len, pool) = (this.m.as_ptr() as usize, this.len, this.pool.clone());
+			this.flushing = Some(Box::pin(async move { pool.spawn(move || msync(addr, len)).await }));
+		}
+		this.poll_pending_flush(cx)
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		self.poll_flush(cx)
+	}
+}
+
+impl AsyncSeek for MmapFileMut {
+	fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+		self.offset = seek_pos(self.len, self.offset, position)?;
+		Ok(())
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
+		Poll::Ready(Ok(self.offset as u64))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::{
+		fs::remove_file,
+		io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+	};
+
+	#[tokio::test]
+	async fn test_mmap_mut() -> Result<()> {
+		let path = "/tmp/ammf_mut";
+		tokio::fs::write(path, b"0123456789").await?;
+		let mut f = MmapFileMut::open(path).await?;
+		f.write_all(b"ab").await?;
+		f.seek(SeekFrom::Start(8)).await?;
+		f.write_all(b"xy").await?;
+		f.flush().await?;
+		assert_eq!(tokio::fs::read(path).await?, b"ab234567xy");
+
+		f.rewind().await?;
+		let mut buf = String::new();
+		f.read_to_string(&mut buf).await?;
+		assert_eq!(buf, "ab234567xy");
+
+		let err = crate::OpenOptions::new().open_mut(path).await.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidInput);
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_grow() -> Result<()> {
+		let path = "/tmp/ammf_mut_grow";
+		let opts = crate::OpenOptions::new().write(true).create(true).truncate(true);
+		let mut f = opts.open_mut(path).await?;
+		assert!(f.is_empty());
+
+		for i in 0..100u8 {
+			f.write_all(&[i; 100]).await?;
+		}
+		assert_eq!((f.len(), f.metadata().await?.len()), (10_000, 10_000));
+		f.reserve(1 << 20).await?;
+		let cap = f.capacity();
+		assert!(cap >= 10_000 + (1 << 20));
+		assert_eq!(f.metadata().await?.len(), 10_000);
+		f.write_all(b"tail").await?;
+		assert_eq!(f.capacity(), cap);
+		f.flush().await?;
+
+		let data = tokio::fs::read(path).await?;
+		assert_eq!(data.len(), 10_004);
+		assert_eq!(&data[9_900..9_902], &[99, 99]);
+		assert_eq!(&data[10_000..], b"tail");
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_append_and_cancel() -> Result<()> {
+		let path = "/tmp/ammf_mut_append";
+		tokio::fs::write(path, b"head").await?;
+		let mut f = crate::OpenOptions::new().append(true).open_mut(path).await?;
+		f.rewind().await?;
+		f.write_all(b"-tail").await?;
+
+		// a flush dropped mid-way is finished by the next call instead of failing it
+		drop(futures::poll!(Box::pin(f.flush_range(0..4))));
+		f.write_all(b"!").await?;
+		f.flush().await?;
+		assert_eq!(tokio::fs::read(path).await?, b"head-tail!");
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_shm() -> Result<()> {
+		let name = "/ammf_shm_test";
+		let _ = MmapFileMut::shm_unlink(name);
+		let mut a = MmapFileMut::shm_open(name, 4096).await?;
+		let mut b = MmapFileMut::shm_open_with_config(name, 16, &Config::default()).await?;
+		assert_eq!((a.len(), b.len()), (4096, 4096));
+		a.write_all(b"ping").await?;
+		a.flush_range(0..4).await?;
+		let mut buf = [0; 4];
+		b.read_exact(&mut buf).await?;
+		assert_eq!(&buf, b"ping");
+		assert!(a.flush_range(0..4097).await.is_err());
+
+		MmapFileMut::shm_unlink(name)?;
+		assert_eq!(MmapFileMut::shm_unlink(name).unwrap_err().kind(), ErrorKind::NotFound);
+		Ok(())
+	}
+}
@@ -0,0 +1,234 @@
+use crate::{Config, MmapFile, MmapFileMut, Result};
+use std::{
+	fs::File as StdFile,
+	io::{Error, ErrorKind},
+	os::unix::fs::OpenOptionsExt,
+	path::Path,
+};
+
+const NO_READ: &str = "mapping a file requires read access";
+const SEALED_WRITE: &str = "sealed mappings can't be opened for writing";
+const NO_WRITE: &str = "mutable mappings require write access";
+
+/// Options for opening an [`MmapFile`], like `std::fs::OpenOptions` plus the mapping settings of [`Config`].
+///
+/// Unlike `std::fs::OpenOptions`, `read` defaults to `true`, since a file can't be mapped without it.
+/// Files opened with `write` support the [`MmapFile`] `AsyncWrite` passthrough.
+///
+/// # Example
+///
+/// ```ignore
+/// let f = MmapFile::options()
+///     .read(true)
+///     .write(true)
+///     .create(true)
+///     .truncate(true)
+///     .open("/path/to/file")
+///     .await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+	read: bool,
+	write: bool,
+	append: bool,
+	truncate: bool,
+	create: bool,
+	create_new: bool,
+	mode: Option<u32>,
+	config: Config,
+}
+
+impl Default for OpenOptions {
+	fn default() -> Self {
+		Self {
+			read: true,
+			write: false,
+			append: false,
+			truncate: false,
+			create: false,
+			create_new: false,
+			mode: None,
+			config: Config::default(),
+		}
+	}
+}
+
+impl OpenOptions {
+	/// Returns options for read-only access, see [`MmapFile::options`].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the option for read access, defaults to `true`.
+	pub fn read(mut self, read: bool) -> Self {
+		self.read = read;
+		self
+	}
+
+	/// Sets the option for write access.
+	pub fn write(mut self, write: bool) -> Self {
+		self.write = write;
+		self
+	}
+
+	/// Sets the option for append mode.
+	pub fn append(mut self, append: bool) -> Self {
+		self.append = append;
+		self
+	}
+
+	/// Sets the option to truncate an existing file to 0 bytes.
+	pub fn truncate(mut self, truncate: bool) -> Self {
+		self.truncate = truncate;
+		self
+	}
+
+	/// Sets the option to create the file if it doesn't exist.
+	pub fn create(mut self, create: bool) -> Self {
+		self.create = create;
+		self
+	}
+
+	/// Sets the option to create the file, failing with `ErrorKind::AlreadyExists` if it exists.
+	pub fn create_new(mut self, create_new: bool) -> Self {
+		self.create_new = create_new;
+		self
+	}
+
+	/// Sets the permission bits of created files, defaults to [`Config::file_mode`] or, without one,
+	/// `0o666` minus the umask.
+	pub fn mode(mut self, mode: u32) -> Self {
+		self.mode = Some(mode);
+		self
+	}
+
+	/// Sets the mapping settings, defaults to [`Config::default`].
+	pub fn config(mut self, config: Config) -> Self {
+		self.config = config;
+		self
+	}
+
+	/// Sets whether the mapping is prefaulted, a shorthand for [`Config::populate`].
+	pub fn populate(mut self, populate: bool) -> Self {
+		self.config = self.config.populate(populate);
+		self
+	}
+
+	/// Sets whether the mapping asks for huge pages, a shorthand for [`Config::huge_pages`].
+	pub fn huge_pages(mut self, huge: bool) -> Self {
+		self.config = self.config.huge_pages(huge);
+		self
+	}
+
+	/// Opens and maps the file at `p` with these options.
+	///
+	/// # Errors
+	///
+	/// Besides the errors of opening the file and those of [`MmapFile::open_with_config`], this returns
+	/// an `ErrorKind::InvalidInput` error if `read` is unset, or if `write` is combined with
+	/// [`Config::seal`].
+	pub async fn open(&self, p: impl AsRef<Path>) -> Result<MmapFile> {
+		if !self.read {
+			return Err(Error::new(ErrorKind::InvalidInput, NO_READ));
+		}
+		if (self.write || self.append) && self.config.seal {
+			return Err(Error::new(ErrorKind::InvalidInput, SEALED_WRITE));
+		}
+		let p = p.as_ref().to_owned();
+		let open = self.opener(&p);
+		MmapFile::map_with(&p, open, &self.config).await
+	}
+
+	/// Opens the file at `p` with these options and maps it for writing, see [`MmapFileMut`].
+	///
+	/// [`Config::seal`] doesn't apply to mutable mappings and is ignored. With `append` set, every write
+	/// goes to the end of the file whatever the cursor's position, like `O_APPEND`.
+	///
+	/// # Errors
+	///
+	/// Besides the errors of opening and mapping the file, this returns an `ErrorKind::InvalidInput`
+	/// error unless `read` and either `write` or `append` are set.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut f = MmapFile::options().write(true).open_mut("/path/to/file").await?;
+	/// ```
+	pub async fn open_mut(&self, p: impl AsRef<Path>) -> Result<MmapFileMut> {
+		if !self.read {
+			return Err(Error::new(ErrorKind::InvalidInput, NO_READ));
+		}
+		if !self.writes() {
+			return Err(Error::new(ErrorKind::InvalidInput, NO_WRITE));
+		}
+		let open = self.opener(p.as_ref());
+		let mut f = MmapFileMut::map_with(open, &self.config).await?;
+		f.set_append(self.append);
+		Ok(f)
+	}
+
+	/// Returns a blocking call opening `p` with these options.
+	///
+	/// Files opened for writing get the config's [`Config::file_mode`] and [`Config::file_owner`], with
+	/// an explicit `mode` taking precedence, like the writers of a [`crate::FileMap`].
+	fn opener(&self, p: &Path) -> impl FnOnce() -> Result<StdFile> + Send + 'static {
+		let policy = self.writes().then(|| self.config.file_policy().with_mode(self.mode));
+		let mut opts = self.std_options(libc::O_NONBLOCK);
+		if let Some(policy) = &policy {
+			opts.mode(policy.create_mode());
+		}
+		let p = p.to_owned();
+		move || {
+			let f = opts.open(p)?;
+			if let Some(policy) = &policy {
+				policy.apply(&f)?;
+			}
+			Ok(f)
+		}
+	}
+
+	/// Returns whether these options open files for writing.
+	pub(crate) fn writes(&self) -> bool {
+		self.write || self.append
+	}
+
+	/// Returns the explicitly set create mode.
+	pub(crate) fn create_mode(&self) -> Option<u32> {
+		self.mode
+	}
+
+	/// Returns the equivalent `open(2)` flags, as used for opening beneath a root.
+	pub(crate) fn flags(&self) -> i32 {
+		let access = match (self.read, self.writes()) {
+			(true, true) => libc::O_RDWR,
+			(false, true) => libc::O_WRONLY,
+			_ => libc::O_RDONLY,
+		};
+		let mut flags = access;
+		for (set, flag) in [
+			(self.append, libc::O_APPEND),
+			(self.truncate, libc::O_TRUNC),
+			(self.create || self.create_new, libc::O_CREAT),
+			(self.create_new, libc::O_EXCL),
+		] {
+			if set {
+				flags |= flag;
+			}
+		}
+		flags
+	}
+
+	/// Returns the equivalent `std::fs::OpenOptions`, with `flags` added.
+	pub(crate) fn std_options(&self, flags: i32) -> std::fs::OpenOptions {
+		let mut opts = StdFile::options();
+		opts.read(self.read)
+			.write(self.write)
+			.append(self.append)
+			.truncate(self.truncate)
+			.create(self.create)
+			.create_new(self.create_new)
+			.mode(self.mode.unwrap_or(0o666))
+			.custom_flags(flags);
+		opts
+	}
+}
@@ -0,0 +1,162 @@
+use crate::{Config, Result, config::with_timeout};
+use std::{
+	fs::{File as StdFile, OpenOptions as StdOpenOptions},
+	io::{Error, ErrorKind, Read, Write},
+	os::{fd::AsRawFd, unix::fs::FileTypeExt},
+	path::Path,
+	pin::Pin,
+	task::{Context, Poll, ready},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf, unix::AsyncFd};
+
+const NOT_A_PIPE: &str = "not a pipe, socket or character device";
+
+/// A FIFO, socket or character device with readiness-driven reads and writes.
+///
+/// [`crate::MmapFile`] only maps regular files. Endpoints that can block, like the read side of an
+/// export pipeline being slow, need their writers parked until the kernel reports them writable
+/// instead of spinning on `EAGAIN`. This registers the descriptor with the runtime's reactor, so a
+/// full pipe makes `poll_write` return `Pending` and wakes it once there's room again.
+#[derive(Debug)]
+pub struct PipeFile {
+	fd: AsyncFd<StdFile>,
+}
+
+impl PipeFile {
+	/// Opens the FIFO (or device) at `p` for reading or writing.
+	///
+	/// Opening a FIFO waits, on the blocking pool, until the other end is opened too.
+	///
+	/// # Errors
+	///
+	/// Besides the errors of `open(2)`, this returns an `ErrorKind::InvalidInput` error for regular files
+	/// and directories, which can't be polled for readiness.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut out = PipeFile::open("/run/export.fifo", true).await?;
+	/// tokio::io::copy(&mut MmapFile::open("/path/to/blob").await?, &mut out).await?;
+	/// ```
+	pub async fn open(p: impl AsRef<Path>, write: bool) -> Result<Self> {
+		Self::open_with_config(p, write, &Config::default()).await
+	}
+
+	/// Like [`PipeFile::open`], waiting for the other end on the config's blocking pool, bounded by
+	/// [`Config::io_timeout`].
+	///
+	/// # Errors
+	///
+	/// Besides the errors of `open`, this returns an `ErrorKind::TimedOut` error if the other end isn't
+	/// opened in time.
+	pub async fn open_with_config(p: impl AsRef<Path>, write: bool, config: &Config) -> Result<Self> {
+		let p = p.as_ref().to_owned();
+		let open = config
+			.blocking_pool
+			.spawn(move || StdOpenOptions::new().read(!write).write(write).open(p));
+		Self::from_std(with_timeout(config.io_timeout, "open", open).await?)
+	}
+
+	/// Wraps an already open pipe, socket or character device, switching it to non-blocking mode.
+	///
+	/// # Errors
+	///
+	/// Returns an `ErrorKind::InvalidInput` error for regular files and directories, and the errors of
+	/// registering the descriptor with the reactor.
+	///
+	/// # Panics
+	///
+	/// Panics when called outside of a Tokio runtime with I/O enabled.
+	pub fn from_std(f: StdFile) -> Result<Self> {
+		let ft = f.metadata()?.file_type();
+		if !(ft.is_fifo() || ft.is_socket() || ft.is_char_device()) {
+			return Err(Error::new(ErrorKind::InvalidInput, NOT_A_PIPE));
+		}
+		let fd = f.as_raw_fd();
+		let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+		if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } != 0 {
+			return Err(Error::last_os_error());
+		}
+		Ok(Self { fd: AsyncFd::new(f)? })
+	}
+
+	/// Returns the wrapped file, still in non-blocking mode.
+	pub fn into_std(self) -> StdFile {
+		self.fd.into_inner()
+	}
+}
+
+impl AsyncRead for PipeFile {
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		loop {
+			let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+			let unfilled = buf.initialize_unfilled();
+			match guard.try_io(|fd| fd.get_ref().read(unfilled)) {
+				Ok(res) => {
+					buf.advance(res?);
+					return Poll::Ready(Ok(()));
+				}
+				Err(_would_block) => continue,
+			}
+		}
+	}
+}
+
+impl AsyncWrite for PipeFile {
+	fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+		loop {
+			let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+			match guard.try_io(|fd| fd.get_ref().write(buf)) {
+				Ok(res) => return Poll::Ready(res),
+				Err(_would_block) => continue,
+			}
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::ffi::CString;
+	use tokio::{
+		fs::remove_file,
+		io::{AsyncReadExt, AsyncWriteExt},
+	};
+
+	#[tokio::test]
+	async fn test_pipe() -> Result<()> {
+		let path = &format!("/tmp/ammf_fifo_pipe_{}", std::process::id());
+		let _ = remove_file(path).await;
+		let c = CString::new(path.as_str()).unwrap();
+		assert_eq!(unsafe { libc::mkfifo(c.as_ptr(), 0o600) }, 0);
+
+		// far more than a pipe buffer holds, so the writer has to wait for the reader
+		let data: Vec<u8> = (0..4 << 20).map(|i| i as u8).collect();
+		let write = async {
+			let mut w = PipeFile::open(path, true).await?;
+			w.write_all(&data).await
+		};
+		let read = async {
+			let mut r = PipeFile::open(path, false).await?;
+			let mut buf = Vec::new();
+			r.read_to_end(&mut buf).await?;
+			Ok::<_, Error>(buf)
+		};
+		let (written, read) = tokio::join!(write, read);
+		written?;
+		assert!(read? == data);
+
+		let err = PipeFile::from_std(StdFile::open("Cargo.toml")?).unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidInput);
+		remove_file(path).await?;
+		Ok(())
+	}
+}
@@ -0,0 +1,263 @@
+use std::{
+	pin::Pin,
+	task::{Context, Poll, ready},
+	time::Duration,
+};
+use tokio::{
+	io::{AsyncRead, ReadBuf},
+	time::{Instant, Sleep},
+};
+
+use crate::Result;
+
+/// Composable stages for readers, e.g. `file_map.get(path).await?.throttle(1 << 20).inflate().crc32()`.
+///
+/// Every stage wraps an `AsyncRead` and is one itself, so they stack in any order and work the same
+/// on [`crate::MmapFile`], [`crate::MmapSlice`] or a plain `tokio::fs::File`. The `inflate` stage needs
+/// the `inflate` feature.
+pub trait ReadPipeline: AsyncRead + Unpin + Sized {
+	/// Limits reads to `bytes_per_sec` on average, see [`Throttle`].
+	fn throttle(self, bytes_per_sec: u64) -> Throttle<Self> {
+		Throttle {
+			r: self,
+			rate: bytes_per_sec.max(1),
+			start: None,
+			read: 0,
+			sleep: None,
+		}
+	}
+
+	/// Computes the CRC32 of everything read, see [`Crc32`].
+	fn crc32(self) -> Crc32<Self> {
+		Crc32 {
+			r: self,
+			h: crc32fast::Hasher::new(),
+		}
+	}
+
+	/// Decompresses a zlib stream, see [`Inflate`].
+	#[cfg(feature = "inflate")]
+	fn inflate(self) -> Inflate<Self> {
+		Inflate {
+			r: self,
+			d: flate2::Decompress::new(true),
+			buf: vec![0; INFLATE_BUF_SIZE].into(),
+			pos: 0,
+			end: 0,
+			eof: false,
+			done: false,
+		}
+	}
+}
+
+impl<R: AsyncRead + Unpin> ReadPipeline for R {}
+
+/// A reader that's paced to a fixed number of bytes per second.
+///
+/// No single read returns more than a second's worth of bytes, and after each one the reader sleeps
+/// until the total read so far is back under the rate, measured from the first read.
+#[derive(Debug)]
+pub struct Throttle<R> {
+	r: R,
+	rate: u64,
+	start: Option<Instant>,
+	read: u64,
+	sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> Throttle<R> {
+	/// Returns the wrapped reader.
+	pub fn into_inner(self) -> R {
+		self.r
+	}
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Throttle<R> {
+	fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		let this = &mut *self;
+		if let Some(sleep) = &mut this.sleep {
+			ready!(sleep.as_mut().poll(cx));
+			this.sleep = None;
+		}
+		let start = *this.start.get_or_insert_with(Instant::now);
+
+		let mut limited = buf.take(buf.remaining().min(this.rate as usize));
+		ready!(Pin::new(&mut this.r).poll_read(cx, &mut limited))?;
+		let n = limited.filled().len();
+		// SAFETY: `limited` is a view of `buf`'s unfilled part and the inner reader initialized `n` bytes of it
+		unsafe { buf.assume_init(n) };
+		buf.advance(n);
+
+		this.read += n as u64;
+		let due = start + Duration::from_secs_f64(this.read as f64 / this.rate as f64);
+		if due > Instant::now() {
+			this.sleep = Some(Box::pin(tokio::time::sleep_until(due)));
+		}
+		Poll::Ready(Ok(()))
+	}
+}
+
+/// A reader that computes the CRC32 of the bytes passing through it.
+#[derive(Debug)]
+pub struct Crc32<R> {
+	r: R,
+	h: crc32fast::Hasher,
+}
+
+impl<R> Crc32<R> {
+	/// Returns the checksum of everything read so far.
+	pub fn checksum(&self) -> u32 {
+		self.h.clone().finalize()
+	}
+
+	/// Returns the wrapped reader.
+	pub fn into_inner(self) -> R {
+		self.r
+	}
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Crc32<R> {
+	fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		let this = &mut *self;
+		let before = buf.filled().len();
+		ready!(Pin::new(&mut this.r).poll_read(cx, buf))?;
+		this.h.update(&buf.filled()[before..]);
+		Poll::Ready(Ok(()))
+	}
+}
+
+/// How much compressed input [`Inflate`] buffers.
+#[cfg(feature = "inflate")]
+const INFLATE_BUF_SIZE: usize = 32 << 10;
+
+#[cfg(feature = "inflate")]
+const TRUNCATED_STREAM: &str = "compressed stream ended early";
+
+/// A reader that decompresses the zlib (RFC 1950) stream read from the wrapped reader.
+///
+/// The stream ends with the compressed one: bytes after it are left unread in the wrapped reader's
+/// input, or in this stage's buffer. Corrupt input fails with `ErrorKind::InvalidData`, input that ends
+/// mid-stream with `ErrorKind::UnexpectedEof`.
+#[cfg(feature = "inflate")]
+pub struct Inflate<R> {
+	r: R,
+	d: flate2::Decompress,
+	buf: Box<[u8]>,
+	pos: usize,
+	end: usize,
+	eof: bool,
+	done: bool,
+}
+
+#[cfg(feature = "inflate")]
+impl<R> Inflate<R> {
+	/// Returns the wrapped reader.
+	pub fn into_inner(self) -> R {
+		self.r
+	}
+}
+
+#[cfg(feature = "inflate")]
+impl<R> std::fmt::Debug for Inflate<R> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Inflate")
+			.field("total_in", &self.d.total_in())
+			.field("total_out", &self.d.total_out())
+			.field("done", &self.done)
+			.finish()
+	}
+}
+
+#[cfg(feature = "inflate")]
+impl<R: AsyncRead + Unpin> AsyncRead for Inflate<R> {
+	fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		use flate2::{FlushDecompress, Status};
+		use std::io::{Error, ErrorKind};
+
+		let this = &mut *self;
+		while !this.done && buf.remaining() > 0 {
+			if this.pos == this.end && !this.eof {
+				let mut input = ReadBuf::new(&mut this.buf);
+				ready!(Pin::new(&mut this.r).poll_read(cx, &mut input))?;
+				(this.pos, this.end) = (0, input.filled().len());
+				this.eof = this.end == 0;
+			}
+			let (total_in, total_out) = (this.d.total_in(), this.d.total_out());
+			let flush = if this.eof {
+				FlushDecompress::Finish
+			} else {
+				FlushDecompress::None
+			};
+			let status = this
+				.d
+				.decompress(&this.buf[this.pos..this.end], buf.initialize_unfilled(), flush)
+				.map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+			let (read, written) = (
+				(this.d.total_in() - total_in) as usize,
+				(this.d.total_out() - total_out) as usize,
+			);
+			this.pos += read;
+			buf.advance(written);
+			this.done = status == Status::StreamEnd;
+			if written > 0 {
+				break;
+			}
+			if this.eof && read == 0 && !this.done {
+				return Poll::Ready(Err(Error::new(ErrorKind::UnexpectedEof, TRUNCATED_STREAM)));
+			}
+		}
+		Poll::Ready(Ok(()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::MmapFile;
+	use tokio::{fs::remove_file, io::AsyncReadExt};
+
+	#[tokio::test(start_paused = true)]
+	async fn test_pipeline() -> Result<()> {
+		let path = "/tmp/ammf_pipeline";
+		let data = vec![7u8; 1000];
+		tokio::fs::write(path, &data).await?;
+
+		let t = Instant::now();
+		let mut r = MmapFile::open(path).await?.throttle(2000).crc32();
+		let mut buf = Vec::new();
+		r.read_to_end(&mut buf).await?;
+		assert!(t.elapsed() >= Duration::from_millis(500));
+		assert_eq!((buf, r.checksum()), (data.clone(), crc32fast::hash(&data)));
+		remove_file(path).await?;
+		Ok(())
+	}
+
+	#[cfg(feature = "inflate")]
+	#[tokio::test]
+	async fn test_inflate() -> Result<()> {
+		use std::io::{ErrorKind, Write};
+
+		let path = "/tmp/ammf_pipeline_inflate";
+		let data: Vec<u8> = (0..100_000).map(|i| (i % 7) as u8).collect();
+		let mut z = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+		z.write_all(&data)?;
+		let compressed = z.finish()?;
+		tokio::fs::write(path, &compressed).await?;
+
+		let mut r = MmapFile::open(path).await?.throttle(1 << 30).inflate().crc32();
+		let mut buf = Vec::new();
+		r.read_to_end(&mut buf).await?;
+		assert_eq!((buf.len(), r.checksum()), (data.len(), crc32fast::hash(&data)));
+
+		tokio::fs::write(path, &compressed[..compressed.len() / 2]).await?;
+		let err = MmapFile::open(path)
+			.await?
+			.inflate()
+			.read_to_end(&mut buf)
+			.await
+			.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+		remove_file(path).await?;
+		Ok(())
+	}
+}
@@ -0,0 +1,20 @@
+//! The traits needed to use the crate's types, for glob-importing.
+//!
+//! ```ignore
+//! use async_mmap_file::prelude::*;
+//!
+//! let mut f = MmapFile::open("/path/to/file").await?;
+//! f.seek(SeekFrom::Start(16)).await?;
+//! let mut lines = f.lines();
+//! while let Some(line) = lines.next().await {
+//!     // ...
+//! }
+//! ```
+//!
+//! `tokio::io::AsyncBufReadExt` is left out on purpose: its `split` and `lines` take precedence over
+//! the zero-copy [`MmapFile::split`](crate::MmapFile::split) and
+//! [`MmapFile::lines`](crate::MmapFile::lines).
+
+pub use crate::{CombineDigest, Digest, FileMap, MmapFile, MmapFileMut, OpenOptions, ReadPipeline};
+pub use futures::{SinkExt, StreamExt};
+pub use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
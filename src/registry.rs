@@ -0,0 +1,93 @@
+use memmap2::Mmap;
+use std::{
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex, Weak},
+	time::{Duration, Instant},
+};
+
+use crate::FileId;
+
+static REGISTRY: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+struct Entry {
+	path: PathBuf,
+	id: FileId,
+	mapped_at: Instant,
+	populated: bool,
+	m: Weak<Mmap>,
+}
+
+/// A live mapping, as returned by [`live_mappings`].
+#[derive(Clone, Debug)]
+pub struct MappingInfo {
+	/// The path the file was opened with.
+	pub path: PathBuf,
+	/// The identity of the mapped file.
+	pub id: FileId,
+	/// The length of the mapping in bytes.
+	pub len: usize,
+	/// How long ago the mapping was created.
+	pub age: Duration,
+	/// The number of handles sharing the mapping, including clones and zero-copy slices.
+	pub handles: usize,
+	/// Whether the mapping was prefaulted at open time.
+	pub populated: bool,
+}
+
+/// Returns every mapping created with [`crate::Config::register`] that is still alive, oldest first.
+///
+/// Meant for diagnosing leaked mappings in production, e.g. by dumping it from an admin endpoint.
+///
+/// # Panics
+///
+/// This function will panic if the mutex is poisoned.
+///
+/// # Example
+///
+/// ```ignore
+/// for m in async_mmap_file::live_mappings() {
+///     println!("{} {} bytes, {} handles, {:?} old", m.path.display(), m.len, m.handles, m.age);
+/// }
+/// ```
+pub fn live_mappings() -> Vec<MappingInfo> {
+	let mut reg = REGISTRY.lock().unwrap();
+	reg.retain(|e| e.m.strong_count() > 0);
+	reg.iter()
+		.filter_map(|e| {
+			let m = e.m.upgrade()?;
+			Some(MappingInfo {
+				path: e.path.clone(),
+				id: e.id,
+				len: m.len(),
+				age: e.mapped_at.elapsed(),
+				handles: Arc::strong_count(&m) - 1,
+				populated: e.populated,
+			})
+		})
+		.collect()
+}
+
+pub(crate) fn register(path: &Path, id: FileId, m: &Arc<Mmap>, populated: bool) {
+	let mut reg = REGISTRY.lock().unwrap();
+	// prune on the way in too, so the registry stays bounded without anyone querying it
+	reg.retain(|e| e.m.strong_count() > 0);
+	reg.push(Entry {
+		path: path.to_owned(),
+		id,
+		mapped_at: Instant::now(),
+		populated,
+		m: Arc::downgrade(m),
+	});
+}
+
+/// Registers `new` under the path of `old`, if `old` was registered.
+pub(crate) fn remapped(old: &Arc<Mmap>, new: &Arc<Mmap>) {
+	let path = {
+		let reg = REGISTRY.lock().unwrap();
+		let Some(e) = reg.iter().find(|e| e.m.as_ptr() == Arc::as_ptr(old)) else {
+			return;
+		};
+		(e.path.clone(), e.id)
+	};
+	register(&path.0, path.1, new, false);
+}
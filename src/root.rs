@@ -0,0 +1,146 @@
+use crate::Result;
+use std::{
+	ffi::{CStr, CString},
+	fs::File as StdFile,
+	io::{Error, ErrorKind},
+	mem,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+	path::{Component, Path, PathBuf},
+};
+
+const ESCAPES_ROOT: &str = "path escapes the root directory";
+const NUL_IN_PATH: &str = "path contains a nul byte";
+
+/// A held directory fd that files are opened relative to, never resolving outside of it.
+#[derive(Debug)]
+pub(crate) struct Root {
+	dir: OwnedFd,
+	path: PathBuf,
+}
+
+impl Root {
+	pub(crate) fn open(dir: &Path) -> Result<Self> {
+		let c = cstring(dir.as_os_str().as_encoded_bytes())?;
+		let fd = unsafe { libc::open(c.as_ptr(), libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+		if fd < 0 {
+			return Err(Error::last_os_error());
+		}
+		Ok(Self {
+			dir: unsafe { OwnedFd::from_raw_fd(fd) },
+			path: dir.to_owned(),
+		})
+	}
+
+	/// The path the root was opened with, which may be stale if the directory was renamed since.
+	pub(crate) fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Opens `path` relative to the root with `openat2(RESOLVE_BENEATH)`.
+	///
+	/// Absolute paths, `..` and symlinks leading outside the root fail with `PermissionDenied`. On
+	/// kernels without `openat2` (< 5.6) this falls back to walking the path one component at a time
+	/// with `openat(O_NOFOLLOW)`, which can't tell where a symlink leads and so rejects all of them.
+	pub(crate) fn open_beneath(&self, path: &str, flags: libc::c_int, mode: libc::mode_t) -> Result<StdFile> {
+		let c = cstring(path.as_bytes())?;
+		let flags = flags | libc::O_CLOEXEC;
+		let mut how: libc::open_how = unsafe { mem::zeroed() };
+		how.flags = flags as u64;
+		how.mode = if flags & libc::O_CREAT != 0 { mode as u64 } else { 0 };
+		how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+		let fd = unsafe {
+			libc::syscall(
+				libc::SYS_openat2,
+				self.dir.as_raw_fd(),
+				c.as_ptr(),
+				&how as *const libc::open_how,
+				size_of::<libc::open_how>(),
+			)
+		};
+		if fd >= 0 {
+			return Ok(unsafe { StdFile::from_raw_fd(fd as libc::c_int) });
+		}
+
+		let err = Error::last_os_error();
+		match err.raw_os_error() {
+			Some(libc::EXDEV) => Err(Error::new(ErrorKind::PermissionDenied, ESCAPES_ROOT)),
+			Some(libc::ENOSYS) => self.openat_fallback(path, flags, mode),
+			_ => Err(err),
+		}
+	}
+
+	/// Opens `path` by walking it from the root, refusing every symlink on the way.
+	fn openat_fallback(&self, path: &str, flags: libc::c_int, mode: libc::mode_t) -> Result<StdFile> {
+		let mut names = Vec::new();
+		for c in Path::new(path).components() {
+			match c {
+				Component::Normal(name) => names.push(cstring(name.as_encoded_bytes())?),
+				Component::CurDir => {}
+				Component::RootDir | Component::ParentDir | Component::Prefix(_) => {
+					return Err(Error::new(ErrorKind::PermissionDenied, ESCAPES_ROOT));
+				}
+			}
+		}
+		let last = names.pop().unwrap_or_else(|| c".".to_owned());
+
+		let mut dir: Option<OwnedFd> = None;
+		for name in &names {
+			let at = dir.as_ref().unwrap_or(&self.dir).as_raw_fd();
+			let flags = libc::O_PATH | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+			dir = Some(openat(at, name, flags, 0)?);
+		}
+		let at = dir.as_ref().unwrap_or(&self.dir).as_raw_fd();
+		openat(at, &last, flags | libc::O_NOFOLLOW, mode).map(StdFile::from)
+	}
+}
+
+/// `openat(2)`, reporting a refused symlink as an escape from the root.
+fn openat(dir: RawFd, name: &CStr, flags: libc::c_int, mode: libc::mode_t) -> Result<OwnedFd> {
+	let fd = unsafe { libc::openat(dir, name.as_ptr(), flags, mode) };
+	if fd >= 0 {
+		return Ok(unsafe { OwnedFd::from_raw_fd(fd) });
+	}
+	let err = Error::last_os_error();
+	match err.raw_os_error() {
+		// O_NOFOLLOW on a symlink, or O_DIRECTORY on one opened with O_PATH
+		Some(libc::ELOOP | libc::ENOTDIR) if is_symlink(dir, name) => {
+			Err(Error::new(ErrorKind::PermissionDenied, ESCAPES_ROOT))
+		}
+		_ => Err(err),
+	}
+}
+
+fn is_symlink(dir: RawFd, name: &CStr) -> bool {
+	let mut st: libc::stat = unsafe { mem::zeroed() };
+	let res = unsafe { libc::fstatat(dir, name.as_ptr(), &mut st, libc::AT_SYMLINK_NOFOLLOW) };
+	res == 0 && st.st_mode & libc::S_IFMT == libc::S_IFLNK
+}
+
+fn cstring(b: &[u8]) -> Result<CString> {
+	CString::new(b).map_err(|_| Error::new(ErrorKind::InvalidInput, NUL_IN_PATH))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_openat_fallback() -> Result<()> {
+		let dir = format!("/tmp/ammf_root_{}", std::process::id());
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(format!("{dir}/inner/sub"))?;
+		std::fs::write(format!("{dir}/inner/sub/file"), b"ok")?;
+		std::os::unix::fs::symlink("/etc", format!("{dir}/inner/out"))?;
+		std::os::unix::fs::symlink("sub", format!("{dir}/inner/alias"))?;
+
+		let root = Root::open(Path::new(&format!("{dir}/inner")))?;
+		let open = |p| root.openat_fallback(p, libc::O_RDONLY | libc::O_CLOEXEC, 0);
+		assert_eq!(std::io::read_to_string(open("./sub/file")?)?, "ok");
+		for p in ["out/passwd", "alias/file", "../inner/sub/file", "/etc/passwd"] {
+			assert_eq!(open(p).unwrap_err().kind(), ErrorKind::PermissionDenied, "{p}");
+		}
+		assert_eq!(open("sub/missing").unwrap_err().kind(), ErrorKind::NotFound);
+		std::fs::remove_dir_all(&dir)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,82 @@
+use bytes::Bytes;
+use std::{
+	io::SeekFrom,
+	pin::Pin,
+	task::{Context, Poll},
+};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, ReadBuf};
+
+use crate::{Result, mmap_file::seek_pos};
+
+/// A byte range of a mapped file with its own cursor, see [`crate::MmapFile::slice`].
+///
+/// Slices share the mapping with the file they were taken from, so handing different ranges of one
+/// file to different tasks costs no copies. Positions are relative to the start of the slice.
+#[derive(Clone, Debug)]
+pub struct MmapSlice {
+	b: Bytes,
+	offset: usize,
+}
+
+impl MmapSlice {
+	pub(crate) fn new(b: Bytes) -> Self {
+		Self { b, offset: 0 }
+	}
+
+	/// Returns the length of the slice.
+	pub fn len(&self) -> usize {
+		self.b.len()
+	}
+
+	/// Returns whether the slice is empty.
+	pub fn is_empty(&self) -> bool {
+		self.b.is_empty()
+	}
+
+	/// Returns the bytes of the slice.
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.b
+	}
+
+	/// Returns the current position of the cursor.
+	pub fn position(&self) -> u64 {
+		self.offset as u64
+	}
+
+	/// Converts the slice into zero-copy `Bytes`.
+	pub fn into_bytes(self) -> Bytes {
+		self.b
+	}
+}
+
+impl AsyncRead for MmapSlice {
+	fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		let start = self.offset.min(self.b.len());
+		let len = buf.remaining().min(self.b.len() - start);
+		buf.put_slice(&self.b[start..start + len]);
+		self.offset = start + len;
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl AsyncBufRead for MmapSlice {
+	fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+		let this = self.get_mut();
+		Poll::Ready(Ok(&this.b[this.offset.min(this.b.len())..]))
+	}
+
+	fn consume(mut self: Pin<&mut Self>, amt: usize) {
+		self.offset = (self.offset + amt).min(self.b.len());
+	}
+}
+
+impl AsyncSeek for MmapSlice {
+	fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+		self.offset = seek_pos(self.b.len(), self.offset, position)?;
+		Ok(())
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
+		Poll::Ready(Ok(self.offset as u64))
+	}
+}
@@ -0,0 +1,211 @@
+use std::{
+	fmt,
+	fs::File as StdFile,
+	io::{Error, Write},
+	mem,
+	os::unix::fs::OpenOptionsExt,
+	path::{Path, PathBuf},
+	pin::Pin,
+	task::{Context, Poll, ready},
+};
+use tokio::{fs::File as TokioFile, io::AsyncWrite};
+
+use crate::{Config, MmapFile, Result, mmap_file::memfd};
+
+const FAILED: &str = "spilling to disk failed earlier";
+
+type Spill = Pin<Box<dyn Future<Output = Result<TokioFile>> + Send>>;
+
+enum State {
+	Mem(Vec<u8>),
+	Spilling(Spill),
+	File(TokioFile),
+	Failed,
+}
+
+/// A write buffer that stays in memory up to a threshold, then spills to an anonymous temporary file.
+///
+/// Meant for building responses or exports whose size isn't known upfront: small ones never touch
+/// the disk, large ones don't take the memory. The spill file is created with `O_TMPFILE`, so it has
+/// no name and disappears with the last handle, even if the process crashes. Either way the result
+/// is read back as an [`MmapFile`] with [`SpillBuffer::finish`].
+///
+/// # Example
+///
+/// ```ignore
+/// let mut buf = SpillBuffer::new("/var/tmp", 8 << 20);
+/// export.write_csv(&mut buf).await?;
+/// let f = buf.finish().await?;
+/// respond(f.into_bytes()).await;
+/// ```
+pub struct SpillBuffer {
+	dir: PathBuf,
+	threshold: usize,
+	state: State,
+	config: Config,
+}
+
+impl SpillBuffer {
+	/// Creates a buffer that spills into a temporary file in `dir` once it would exceed `threshold` bytes.
+	pub fn new(dir: impl AsRef<Path>, threshold: usize) -> Self {
+		Self::with_config(dir, threshold, Config::default())
+	}
+
+	/// Like [`SpillBuffer::new`], spilling on the config's blocking pool and mapping the result with it.
+	///
+	/// The spill file gets the config's [`Config::file_mode`] and [`Config::file_owner`], its mode
+	/// defaults to `0o600`.
+	pub fn with_config(dir: impl AsRef<Path>, threshold: usize, config: Config) -> Self {
+		Self {
+			dir: dir.as_ref().to_owned(),
+			threshold,
+			state: State::Mem(Vec::new()),
+			config,
+		}
+	}
+
+	/// Returns whether the buffer has spilled to disk.
+	pub fn is_spilled(&self) -> bool {
+		!matches!(self.state, State::Mem(_))
+	}
+
+	/// Finishes writing and maps the contents.
+	///
+	/// In-memory contents are moved into a memfd, so the result is an [`MmapFile`] either way.
+	///
+	/// # Errors
+	///
+	/// This function returns an error if spilling failed earlier, or if flushing or mapping fails.
+	pub async fn finish(mut self) -> Result<MmapFile> {
+		futures::future::poll_fn(|cx| self.poll_spilled(cx)).await?;
+		let config = self.config.clone();
+		match mem::replace(&mut self.state, State::Failed) {
+			State::Mem(data) => {
+				let open = move || {
+					let mut f = memfd(c"async_mmap_file", 0)?;
+					f.write_all(&data)?;
+					Ok(f)
+				};
+				MmapFile::map_with(Path::new("memfd:spill"), open, &config).await
+			}
+			State::File(mut f) => {
+				tokio::io::AsyncWriteExt::flush(&mut f).await?;
+				let f = f.into_std().await;
+				MmapFile::map_with(&self.dir, move || Ok(f), &config).await
+			}
+			State::Spilling(_) | State::Failed => Err(Error::other(FAILED)),
+		}
+	}
+
+	/// Drives a spill in progress, if any, to completion.
+	fn poll_spilled(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		let State::Spilling(spill) = &mut self.state else {
+			return Poll::Ready(Ok(()));
+		};
+		let (state, res) = match ready!(spill.as_mut().poll(cx)) {
+			Ok(f) => (State::File(f), Ok(())),
+			Err(err) => (State::Failed, Err(err)),
+		};
+		self.state = state;
+		Poll::Ready(res)
+	}
+
+	/// Starts moving the buffered data into a temporary file.
+	fn spill(&mut self, data: Vec<u8>) {
+		let (dir, pool, policy) = (
+			self.dir.clone(),
+			self.config.blocking_pool.clone(),
+			self.config.file_policy(),
+		);
+		let spill = async move {
+			let f = pool
+				.spawn(move || {
+					let mut f = tmpfile(&dir, policy.create_mode_or(0o600))?;
+					policy.apply(&f)?;
+					f.write_all(&data)?;
+					Ok(f)
+				})
+				.await?;
+			Ok(TokioFile::from_std(f))
+		};
+		self.state = State::Spilling(Box::pin(spill));
+	}
+}
+
+/// Opens an unnamed temporary file in `dir`.
+fn tmpfile(dir: &Path, mode: u32) -> Result<StdFile> {
+	StdFile::options()
+		.read(true)
+		.write(true)
+		.mode(mode)
+		.custom_flags(libc::O_TMPFILE)
+		.open(dir)
+}
+
+impl fmt::Debug for SpillBuffer {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SpillBuffer")
+			.field("dir", &self.dir)
+			.field("threshold", &self.threshold)
+			.field("spilled", &self.is_spilled())
+			.finish()
+	}
+}
+
+impl AsyncWrite for SpillBuffer {
+	fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+		let this = &mut *self;
+		loop {
+			match &mut this.state {
+				State::Mem(v) if v.len() + buf.len() <= this.threshold => {
+					v.extend_from_slice(buf);
+					return Poll::Ready(Ok(buf.len()));
+				}
+				State::Mem(v) => {
+					let data = mem::take(v);
+					this.spill(data);
+				}
+				State::Spilling(_) => ready!(this.poll_spilled(cx))?,
+				State::File(f) => return Pin::new(f).poll_write(cx, buf),
+				State::Failed => return Poll::Ready(Err(Error::other(FAILED))),
+			}
+		}
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		let this = &mut *self;
+		ready!(this.poll_spilled(cx))?;
+		match &mut this.state {
+			State::File(f) => Pin::new(f).poll_flush(cx),
+			State::Failed => Poll::Ready(Err(Error::other(FAILED))),
+			_ => Poll::Ready(Ok(())),
+		}
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+		self.poll_flush(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::io::AsyncWriteExt;
+
+	#[tokio::test]
+	async fn test_spill() -> Result<()> {
+		let mut small = SpillBuffer::new("/tmp", 16);
+		small.write_all(b"0123456789").await?;
+		assert!(!small.is_spilled());
+		assert_eq!(small.finish().await?.as_bytes(), b"0123456789");
+
+		let mut big = SpillBuffer::new("/tmp", 16);
+		big.write_all(b"0123456789").await?;
+		big.write_all(b"0123456789").await?;
+		assert!(big.is_spilled());
+		big.write_all(b"!").await?;
+		let f = big.finish().await?;
+		assert_eq!(f.as_bytes(), b"01234567890123456789!");
+		Ok(())
+	}
+}
@@ -0,0 +1,174 @@
+use bytes::{Buf, Bytes};
+use futures::Stream;
+use std::{
+	mem,
+	pin::Pin,
+	task::{Context, Poll, ready},
+	time::Duration,
+};
+use tokio::time::Sleep;
+
+use crate::{MmapFile, Result};
+
+/// A stream of zero-copy records separated by a delimiter byte, see [`crate::MmapFile::split`] and
+/// [`crate::MmapFile::lines`].
+///
+/// Records never include the delimiter. Like `BufRead::split`, a trailing delimiter does not produce
+/// an empty final record.
+#[derive(Clone, Debug)]
+pub struct Split {
+	rest: Bytes,
+	delim: u8,
+	strip_cr: bool,
+}
+
+impl Split {
+	pub(crate) fn new(rest: Bytes, delim: u8) -> Self {
+		Self {
+			rest,
+			delim,
+			strip_cr: false,
+		}
+	}
+
+	/// Splits `rest` into lines, which also drop a trailing `\r` like `BufRead::lines`.
+	pub(crate) fn lines(rest: Bytes) -> Self {
+		Self {
+			strip_cr: true,
+			..Self::new(rest, b'\n')
+		}
+	}
+
+	fn next_record(&mut self) -> Option<Bytes> {
+		if self.rest.is_empty() {
+			return None;
+		}
+		let mut rec = match memchr::memchr(self.delim, &self.rest) {
+			Some(i) => {
+				let rec = self.rest.split_to(i);
+				self.rest.advance(1);
+				rec
+			}
+			None => mem::take(&mut self.rest),
+		};
+		if self.strip_cr && rec.ends_with(b"\r") {
+			rec.truncate(rec.len() - 1);
+		}
+		Some(rec)
+	}
+}
+
+impl Stream for Split {
+	type Item = Bytes;
+
+	fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Bytes>> {
+		Poll::Ready(self.get_mut().next_record())
+	}
+}
+
+/// A stream of zero-copy chunks of a mapping, see [`crate::MmapFile::stream_from`] and
+/// [`crate::MmapFile::read_backwards`].
+///
+/// Reverse streams yield the unaligned tail of the file first, so every following chunk starts on a
+/// chunk-size boundary.
+#[derive(Clone, Debug)]
+pub struct Chunks {
+	rest: Bytes,
+	size: usize,
+	rev: bool,
+}
+
+impl Chunks {
+	pub(crate) fn new(rest: Bytes, size: usize, rev: bool) -> Self {
+		Self { rest, size, rev }
+	}
+
+	/// Sets the size of the yielded chunks, the last (or, in reverse, first) chunk may be shorter.
+	///
+	/// # Panics
+	///
+	/// Panics if `size` is 0.
+	pub fn chunk_size(mut self, size: usize) -> Self {
+		assert!(size > 0, "chunk size must be non-zero");
+		self.size = size;
+		self
+	}
+
+	fn next_chunk(&mut self) -> Option<Bytes> {
+		if self.rest.is_empty() {
+			return None;
+		}
+		if !self.rev {
+			return Some(self.rest.split_to(self.size.min(self.rest.len())));
+		}
+		let tail = match self.rest.len() % self.size {
+			0 => self.size,
+			n => n,
+		};
+		Some(self.rest.split_off(self.rest.len() - tail))
+	}
+}
+
+impl Stream for Chunks {
+	type Item = Bytes;
+
+	fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Bytes>> {
+		Poll::Ready(self.get_mut().next_chunk())
+	}
+}
+
+/// A stream of the data appended to a file, like `tail -f`, see [`crate::MmapFile::follow`].
+///
+/// Once the mapping is exhausted the file is `fstat`ed every poll interval and remapped when it grew,
+/// yielding everything new as one zero-copy chunk.
+#[derive(Debug)]
+pub struct Follow {
+	f: MmapFile,
+	pos: usize,
+	interval: Duration,
+	sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Follow {
+	pub(crate) fn new(f: MmapFile, pos: usize) -> Self {
+		Self {
+			f,
+			pos,
+			interval: Duration::from_millis(250),
+			sleep: None,
+		}
+	}
+
+	/// Sets how often the file is checked for growth, defaults to 250ms.
+	pub fn poll_interval(mut self, interval: Duration) -> Self {
+		self.interval = interval;
+		self
+	}
+}
+
+impl Stream for Follow {
+	type Item = Result<Bytes>;
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes>>> {
+		let this = self.get_mut();
+		loop {
+			let len = this.f.as_bytes().len();
+			if this.pos < len {
+				let chunk = this.f.to_bytes(this.pos..);
+				this.pos = len;
+				return Poll::Ready(Some(chunk));
+			}
+			if let Some(sleep) = &mut this.sleep {
+				ready!(sleep.as_mut().poll(cx));
+				this.sleep = None;
+				continue;
+			}
+			if let Err(err) = this.f.remap_if_grown() {
+				return Poll::Ready(Some(Err(err)));
+			}
+			if this.f.as_bytes().len() == len {
+				this.sleep = Some(Box::pin(tokio::time::sleep(this.interval)));
+			}
+		}
+	}
+}
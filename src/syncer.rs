@@ -0,0 +1,237 @@
+use crate::{BlockingPool, Config, Durability, FileId, Result, config::with_timeout};
+use std::{
+	collections::HashMap,
+	fs::File as StdFile,
+	io::Error,
+	os::fd::{AsFd, OwnedFd},
+	path::Path,
+	sync::Arc,
+	time::Duration,
+};
+use tokio::{
+	fs::File as TokioFile,
+	io::AsyncWriteExt,
+	sync::{mpsc, oneshot},
+};
+
+const SYNCER_CLOSED: &str = "syncer is shut down";
+
+type Done = oneshot::Sender<Result<()>>;
+
+struct Request {
+	fd: OwnedFd,
+	data_only: bool,
+	done: Done,
+}
+
+/// Batches and deduplicates fsync requests.
+///
+/// Requests that arrive while a batch is being synced are queued and grouped by inode, so a hundred
+/// writers syncing the same file cost one `fsync`. Every caller's future resolves once a sync that
+/// started *after* its request was queued has completed, which is exactly the durability guarantee a
+/// direct `sync_all` call gives for the data that reached the kernel before the request.
+///
+/// A `tokio::fs::File` returns from `write_all` while its last write may still be running in the
+/// background, so it has to be synced with [`Syncer::sync_tokio`], which flushes it first.
+///
+/// The syncs run on the configured [`BlockingPool`]; the worker task exits once every clone is dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// let syncer = Syncer::new();
+/// file.write_all(b"data").await?;
+/// syncer.sync_tokio(&mut file, true).await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Syncer {
+	tx: mpsc::UnboundedSender<Request>,
+	timeout: Option<Duration>,
+	pool: Arc<BlockingPool>,
+	durability: Durability,
+}
+
+impl Syncer {
+	/// Creates a syncer and spawns its worker task.
+	///
+	/// # Panics
+	///
+	/// This function will panic if called outside of a tokio runtime.
+	pub fn new() -> Self {
+		Self::with_config(&Config::default())
+	}
+
+	/// Creates a syncer whose calls fail with `ErrorKind::TimedOut` after `Config::io_timeout`, and
+	/// whose [`Syncer::sync`] follows `Config::durability`.
+	///
+	/// # Panics
+	///
+	/// This function will panic if called outside of a tokio runtime.
+	pub fn with_config(config: &Config) -> Self {
+		let (tx, rx) = mpsc::unbounded_channel();
+		let pool = config.blocking_pool.clone();
+		tokio::spawn(run(rx, pool.clone()));
+		Self {
+			tx,
+			timeout: config.io_timeout,
+			pool,
+			durability: config.durability,
+		}
+	}
+
+	/// Waits until all data and metadata of `f` is durable, like `File::sync_all`.
+	///
+	/// Only writes that have already reached the kernel are covered, use [`Syncer::sync_tokio`] for
+	/// tokio files.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if the fd can't be duplicated or the sync fails.
+	pub async fn sync_all(&self, f: &impl AsFd) -> Result<()> {
+		self.submit(f.as_fd().try_clone_to_owned()?, false).await
+	}
+
+	/// Waits until the data of `f` is durable, like `File::sync_data`.
+	///
+	/// Only writes that have already reached the kernel are covered, use [`Syncer::sync_tokio`] for
+	/// tokio files.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if the fd can't be duplicated or the sync fails.
+	pub async fn sync_data(&self, f: &impl AsFd) -> Result<()> {
+		self.submit(f.as_fd().try_clone_to_owned()?, true).await
+	}
+
+	/// Waits for the pending writes of a tokio file to finish, then until it's durable.
+	///
+	/// Syncs only the data, like [`Syncer::sync_data`], if `data_only` is set, and everything like
+	/// [`Syncer::sync_all`] otherwise.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if a pending write fails, the fd can't be duplicated or the
+	/// sync fails.
+	pub async fn sync_tokio(&self, f: &mut TokioFile, data_only: bool) -> Result<()> {
+		f.flush().await?;
+		self.submit(f.as_fd().try_clone_to_owned()?, data_only).await
+	}
+
+	/// Like [`Syncer::sync_tokio`], syncing as far as the config's [`Config::durability`] asks for.
+	///
+	/// With [`Durability::Flush`] this only waits for the pending writes.
+	///
+	/// # Errors
+	///
+	/// Returns the same errors as [`Syncer::sync_tokio`].
+	pub async fn sync(&self, f: &mut TokioFile) -> Result<()> {
+		match self.durability {
+			Durability::Flush => f.flush().await,
+			Durability::Data => self.sync_tokio(f, true).await,
+			Durability::Full => self.sync_tokio(f, false).await,
+		}
+	}
+
+	/// Opens `path` (a file or a directory) and waits until it is durable.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if the path can't be opened or the sync fails.
+	pub async fn sync_path(&self, path: impl AsRef<Path>) -> Result<()> {
+		let path = path.as_ref().to_owned();
+		let open = self.pool.spawn(move || StdFile::open(path));
+		let f = with_timeout(self.timeout, "open", open).await?;
+		self.submit(f.into(), false).await
+	}
+
+	async fn submit(&self, fd: OwnedFd, data_only: bool) -> Result<()> {
+		let (done, rx) = oneshot::channel();
+		self.tx
+			.send(Request { fd, data_only, done })
+			.map_err(|_| Error::other(SYNCER_CLOSED))?;
+		let done = async { rx.await.map_err(|_| Error::other(SYNCER_CLOSED))? };
+		with_timeout(self.timeout, "fsync", done).await
+	}
+}
+
+impl Default for Syncer {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+async fn run(mut rx: mpsc::UnboundedReceiver<Request>, pool: Arc<BlockingPool>) {
+	while let Some(req) = rx.recv().await {
+		let mut batch = vec![req];
+		while let Ok(req) = rx.try_recv() {
+			batch.push(req);
+		}
+		// a panic in the blocking task drops the senders, which callers see as SYNCER_CLOSED
+		let _ = pool
+			.spawn(move || {
+				sync_batch(batch);
+				Ok(())
+			})
+			.await;
+	}
+}
+
+fn sync_batch(batch: Vec<Request>) {
+	let mut groups: HashMap<FileId, (StdFile, bool, Vec<Done>)> = HashMap::new();
+	for Request { fd, data_only, done } in batch {
+		let f = StdFile::from(fd);
+		match FileId::of(&f) {
+			Ok(id) => {
+				let (_, group_data_only, waiters) = groups.entry(id).or_insert_with(|| (f, true, vec![]));
+				*group_data_only &= data_only;
+				waiters.push(done);
+			}
+			Err(err) => {
+				let _ = done.send(Err(err));
+			}
+		}
+	}
+
+	for (f, data_only, waiters) in groups.into_values() {
+		let res = if data_only { f.sync_data() } else { f.sync_all() };
+		for done in waiters {
+			let res = res
+				.as_ref()
+				.map(|_| ())
+				.map_err(|err| Error::new(err.kind(), err.to_string()));
+			let _ = done.send(res);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::fs::{File, remove_file};
+
+	#[tokio::test]
+	async fn test_syncer() -> Result<()> {
+		let path = "/tmp/ammf_syncer";
+		let syncer = Syncer::new();
+		let mut a = File::create(path).await?;
+		let b = File::open(path).await?;
+		a.write_all(b"data").await?;
+		let (ra, rb, rp) = tokio::join!(
+			syncer.sync_tokio(&mut a, false),
+			syncer.sync_data(&b),
+			syncer.sync_path("/tmp")
+		);
+		ra?;
+		rb?;
+		rp?;
+		assert_eq!(tokio::fs::read(path).await?, b"data");
+
+		a.write_all(b"more").await?;
+		let full = Syncer::with_config(&Config::new().durability(Durability::Full));
+		full.sync(&mut a).await?;
+		assert_eq!(tokio::fs::read(path).await?, b"datamore");
+		assert!(syncer.sync_path("/tmp/ammf_syncer_missing").await.is_err());
+		remove_file(path).await?;
+		Ok(())
+	}
+}
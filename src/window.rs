@@ -0,0 +1,226 @@
+use crate::{
+	Config, Result,
+	config::with_timeout,
+	mmap_file::{OS_PAGE_SIZE, check_regular},
+};
+use memmap2::Mmap;
+use std::{
+	fs::File as StdFile,
+	io::{Error, ErrorKind, SeekFrom},
+	ops::Range,
+	os::unix::fs::OpenOptionsExt,
+	path::Path,
+	pin::Pin,
+	task::{Context, Poll},
+};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, ReadBuf};
+
+const TOO_LARGE: &str = "file exceeds the configured size limit";
+const INVALID_POSITION: &str = "invalid position";
+
+/// A read-only file mapped through a sliding window of fixed size.
+///
+/// [`crate::MmapFile`] maps whole files, which fails on 32-bit targets or under `RLIMIT_AS` once a
+/// file is larger than the free address space. A window only ever maps `window` bytes of the file and
+/// remaps when a read or seek leaves them, so files of any size can be read with bounded address
+/// space. Positions are `u64` throughout.
+///
+/// Remapping happens inline in `poll_read` and `read_at`. Like a lazily mapped `MmapFile`, the first
+/// read of each page faults it in from the file, so windows should be sized to amortize the `mmap(2)`
+/// call over many reads, e.g. a few MB for sequential scans. The file's length is fixed when it's
+/// opened; truncating it while it's mapped has the same `SIGBUS` hazard as with `MmapFile`.
+#[derive(Debug)]
+pub struct MmapWindow {
+	f: StdFile,
+	len: u64,
+	window: usize,
+	m: Option<(u64, Mmap)>,
+	offset: u64,
+}
+
+impl MmapWindow {
+	/// Opens the file at path `p` for reading through windows of `window` bytes.
+	///
+	/// `window` is rounded up to a multiple of the page size. Opening and validating the file use the
+	/// config's blocking pool, [`Config::io_timeout`], [`Config::max_file_size`] and
+	/// [`Config::validator`]; the other mapping settings don't apply to windows.
+	///
+	/// # Arguments
+	///
+	/// * `p` - The path of the file.
+	/// * `window` - The most bytes of the file mapped at once.
+	/// * `config` - The settings to open the file with.
+	///
+	/// # Errors
+	///
+	/// This function will return an error if the file cannot be opened, is not a regular file or is
+	/// rejected by the config.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// let mut f = MmapWindow::open("/data/10gb.bin", 16 << 20, &Config::default()).await?;
+	/// f.seek(SeekFrom::Start(8 << 30)).await?;
+	/// let mut record = [0; 512];
+	/// f.read_exact(&mut record).await?;
+	/// ```
+	pub async fn open(p: impl AsRef<Path>, window: usize, config: &Config) -> Result<Self> {
+		let p = p.as_ref().to_owned();
+		let (max_size, validator) = (config.max_file_size, config.validator.clone());
+		let open = config.blocking_pool.spawn(move || -> Result<(StdFile, u64)> {
+			let f = StdFile::options().read(true).custom_flags(libc::O_NONBLOCK).open(p)?;
+			let meta = f.metadata()?;
+			check_regular(meta.file_type())?;
+			if max_size.is_some_and(|max| meta.len() > max) {
+				return Err(Error::new(ErrorKind::FileTooLarge, TOO_LARGE));
+			}
+			if let Some(validate) = &validator {
+				(validate.0)(&f, &meta)?;
+			}
+			Ok((f, meta.len()))
+		});
+		let (f, len) = with_timeout(config.io_timeout, "open", open).await?;
+		Ok(Self {
+			f,
+			len,
+			window: window.max(1).next_multiple_of(*OS_PAGE_SIZE),
+			m: None,
+			offset: 0,
+		})
+	}
+
+	/// Returns the length of the file.
+	pub fn len(&self) -> u64 {
+		self.len
+	}
+
+	/// Returns whether the file is empty.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Returns the size of the window.
+	pub fn window_size(&self) -> usize {
+		self.window
+	}
+
+	/// Returns the range of the file that's currently mapped, if any.
+	pub fn mapped_range(&self) -> Option<Range<u64>> {
+		self.m.as_ref().map(|(start, m)| *start..*start + m.len() as u64)
+	}
+
+	/// Returns the current position of the cursor.
+	pub fn position(&self) -> u64 {
+		self.offset
+	}
+
+	/// Reads bytes at `offset` into `buf` without moving the cursor, remapping the window if needed.
+	///
+	/// Like `pread(2)` this returns fewer bytes than requested at the end of the file, and at most the
+	/// bytes up to the end of the window containing `offset`, and 0 bytes past the end of the file.
+	///
+	/// # Errors
+	///
+	/// Returns the `mmap(2)` error if the window can't be mapped.
+	pub fn read_at(&mut self, buf: &mut [u8], offset: u64) -> Result<usize> {
+		let b = self.bytes_at(offset)?;
+		let n = buf.len().min(b.len());
+		buf[..n].copy_from_slice(&b[..n]);
+		Ok(n)
+	}
+
+	/// Returns the mapped bytes from `pos` to the end of its window, mapping that window first if it
+	/// isn't the current one.
+	fn bytes_at(&mut self, pos: u64) -> Result<&[u8]> {
+		if pos >= self.len {
+			return Ok(&[]);
+		}
+		let mapped = self.mapped_range().is_some_and(|r| r.contains(&pos));
+		if !mapped {
+			// drop the old window first so both are never mapped at once
+			self.m = None;
+			let start = pos - pos % self.window as u64;
+			let len = (self.len - start).min(self.window as u64) as usize;
+			let m = unsafe { memmap2::MmapOptions::new().offset(start).len(len).map(&self.f)? };
+			self.m = Some((start, m));
+		}
+		let (start, m) = self.m.as_ref().expect("window was just mapped");
+		Ok(&m[(pos - start) as usize..])
+	}
+}
+
+impl AsyncRead for MmapWindow {
+	fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+		let this = &mut *self;
+		let b = this.bytes_at(this.offset)?;
+		let len = buf.remaining().min(b.len());
+		buf.put_slice(&b[..len]);
+		this.offset += len as u64;
+		Poll::Ready(Ok(()))
+	}
+}
+
+/// `fill_buf` hands out the rest of the current window, so `tokio::io::copy_buf` and `read_line` work
+/// straight from the mapping; consuming past the window's end maps the next one on the following call.
+impl AsyncBufRead for MmapWindow {
+	fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+		let this = self.get_mut();
+		Poll::Ready(this.bytes_at(this.offset))
+	}
+
+	fn consume(mut self: Pin<&mut Self>, amt: usize) {
+		self.offset = self.offset.saturating_add(amt as u64).min(self.len);
+	}
+}
+
+impl AsyncSeek for MmapWindow {
+	fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+		let pos = match position {
+			SeekFrom::Start(offset) => Some(offset),
+			SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+			SeekFrom::Current(offset) => self.offset.checked_add_signed(offset),
+		};
+		self.offset = pos
+			.filter(|&pos| pos <= self.len)
+			.ok_or_else(|| Error::new(ErrorKind::InvalidInput, INVALID_POSITION))?;
+		Ok(())
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
+		Poll::Ready(Ok(self.offset))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::{
+		fs::remove_file,
+		io::{AsyncReadExt, AsyncSeekExt},
+	};
+
+	#[tokio::test]
+	async fn test_window() -> Result<()> {
+		let path = "/tmp/ammf_window";
+		let data: Vec<u8> = (0..3 * *OS_PAGE_SIZE + 100).map(|i| (i % 251) as u8).collect();
+		tokio::fs::write(path, &data).await?;
+
+		let mut f = MmapWindow::open(path, 1, &Config::default()).await?;
+		assert_eq!(f.window_size(), *OS_PAGE_SIZE);
+		let mut buf = Vec::new();
+		f.read_to_end(&mut buf).await?;
+		assert_eq!(buf, data);
+		assert_eq!(f.mapped_range(), Some(3 * *OS_PAGE_SIZE as u64..data.len() as u64));
+
+		f.seek(SeekFrom::Start(10)).await?;
+		let mut b = [0; 16];
+		f.read_exact(&mut b).await?;
+		assert_eq!(&b, &data[10..26]);
+		let edge = *OS_PAGE_SIZE as u64 - 8;
+		assert_eq!(f.read_at(&mut b, edge)?, 8);
+		assert_eq!(f.read_at(&mut b, data.len() as u64)?, 0);
+		assert!(f.seek(SeekFrom::End(1)).await.is_err());
+		remove_file(path).await?;
+		Ok(())
+	}
+}